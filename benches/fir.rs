@@ -0,0 +1,62 @@
+#![feature(test)]
+
+extern crate modem;
+extern crate test;
+
+use modem::fir::FIRFilter;
+use modem::fir::design::{self, Window};
+use modem::freq::Freq;
+use test::Bencher;
+
+// Samples filtered per iteration.
+const SAMPLES: usize = 4096;
+
+fn input() -> Vec<f32> {
+    (0..SAMPLES).map(|i| (i as f32 * 0.1).sin()).collect()
+}
+
+fn lowpass(taps: usize) -> Vec<f32> {
+    design::lowpass(Freq::new(1000, 10000), taps, Window::Hamming)
+}
+
+fn bench_add(b: &mut Bencher, taps: usize) {
+    let input = input();
+    let mut f = FIRFilter::new(lowpass(taps));
+
+    b.bytes = (SAMPLES * 4) as u64;
+    b.iter(|| {
+        for &x in &input {
+            test::black_box(f.add(x));
+        }
+    });
+}
+
+fn bench_process(b: &mut Bencher, taps: usize) {
+    let input = input();
+    let mut output = vec![0.0; SAMPLES];
+    let mut f = FIRFilter::new(lowpass(taps));
+
+    b.bytes = (SAMPLES * 4) as u64;
+    b.iter(|| {
+        f.process(&input[..], &mut output[..]);
+        test::black_box(&output);
+    });
+}
+
+#[bench]
+fn add_33(b: &mut Bencher) { bench_add(b, 33) }
+
+#[bench]
+fn process_33(b: &mut Bencher) { bench_process(b, 33) }
+
+#[bench]
+fn add_65(b: &mut Bencher) { bench_add(b, 65) }
+
+#[bench]
+fn process_65(b: &mut Bencher) { bench_process(b, 65) }
+
+#[bench]
+fn add_511(b: &mut Bencher) { bench_add(b, 511) }
+
+#[bench]
+fn process_511(b: &mut Bencher) { bench_process(b, 511) }
@@ -2,21 +2,70 @@ extern crate getopts;
 extern crate modem;
 extern crate num;
 
-mod util;
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::{BufReader, Read};
 
-use modem::{carrier, freq, demodulator, fir};
-use util::Read16;
+use modem::{conv, demodulator, digital, fir, pll, prbs, raw, sigmf, timing, wav};
+use modem::digital::{DigitalPhasor, Slicer};
+use modem::digital::llr::{Metric, NoiseEstimate, SoftSlicer};
+use modem::fir::design::Window;
+use modem::freq::Freq;
+use modem::nco::NCO;
+use modem::rates::Rates;
+use modem::carrier::{self, Carrier};
+use modem::pulse::Pulse;
+use modem::resample::{Resampler, TAPS_PER_PHASE};
+use modem::scramble::Scrambler;
 
-const SAMPLE_RATE: usize = 10000;
+// The maximum amplitude of the expected waveform.
+const AMPLITUDE: f32 = 1.0;
+
+// Number of taps in the Hilbert transform filter.
+const HILBERT_TAPS: usize = 23;
+
+// Carrier loop bandwidth (normalized to the sample rate) and damping for locking onto
+// the preamble.
+const LOOP_BANDWIDTH: f32 = 0.002;
+const LOOP_DAMPING: f32 = 0.707;
+
+// Symbol timing loop bandwidth (normalized to the baud rate.)
+const TIMING_BANDWIDTH: f32 = 0.01;
+
+// Carrier tracking loop bandwidth after the preamble (normalized to the baud rate.)
+const TRACKING_BANDWIDTH: f32 = 0.01;
+
+// Number of symbols the noise estimate for soft decisions averages over.
+const NOISE_SYMBOLS: usize = 100;
 
 const USAGE: &'static str = "
-    Demodulate a waveform on stdin to i/q samples on stdout";
+    Demodulate a waveform on stdin to bits on stdout";
 
 fn main() {
     let mut parser = getopts::Options::new();
 
     parser.optflag("h", "help", "show usage")
-          .optopt("b", "", "baud rate (symbols/sec)", "RATE");
+          .optopt("m", "", "digital modulation to use", "MOD")
+          .optopt("r", "", "sample rate (samples/sec)", "RATE")
+          .optopt("b", "", "baud rate (symbols/sec)", "RATE")
+          .optopt("c", "", "carrier frequency (Hz)", "FREQ")
+          .optopt("p", "", "preamble cycles to lock onto", "CYCLES")
+          .optopt("", "mapping", "constellation labelling (natural, gray, dvbs2)", "MAP")
+          .optopt("", "pulse", "pulse shape (rrc, rc, gaussian, halfsine)", "SHAPE")
+          .optopt("", "rolloff", "pulse roll-off factor, or BT for gaussian", "FACTOR")
+          .optopt("", "span", "pulse length (symbols)", "SYMBOLS")
+          .optopt("", "in-rate", "resample the input from the given rate", "RATE")
+          .optopt("", "ber", "check the bits against a test pattern (prbs7, prbs9, \
+                              prbs15, prbs23, prbs31) and report the error rate",
+                  "PATTERN")
+          .optopt("", "scramble", "descramble the bits (v22, v32, 80211, dvbs)", "NAME")
+          .optopt("", "fec", "convolutional code rate (1/2, 2/3, 3/4, 5/6, 7/8)", "RATE")
+          .optopt("", "code", "convolutional code as K:GEN,GEN,... in octal, by default \
+                               7:171,133", "CODE")
+          .optopt("", "format", "raw sample format (f32, s16be, cs16, cu8, ...)", "FMT")
+          .optflag("", "wav", "input a WAV file, mono or stereo IQ, at its own rate")
+          .optopt("", "sigmf", "input a SigMF recording, BASE.sigmf-meta and -data",
+                  "BASE");
 
     let args: Vec<String> = std::env::args().skip(1).collect();
     let opts = parser.parse(&args).unwrap();
@@ -26,125 +75,392 @@ fn main() {
         return;
     }
 
-    let input = std::io::stdin().iter_16().map(|x| x as f32);
+    // A SigMF recording carries its own parameters, which the options override.
+    let meta = opts.opt_str("sigmf").map(|base| {
+        let f = File::open(format!("{}.sigmf-meta", base))
+                    .expect("can't open SigMF metadata");
+        (base, sigmf::Meta::read(f).expect("invalid SigMF metadata"))
+    });
+
+    // The digital modulation to use.
+    let dmod = opts.opt_str("m").or(meta.as_ref().and_then(|m| m.1.modulation.clone()))
+                   .expect("digital modulation is required");
+
+    // The bits per symbol, if the modulation is QAM.
+    let qam_bits = digital::qam::parse_bits(&dmod);
+
+    // The sample rate to use.
+    let sr: usize = match opts.opt_str("r") {
+        Some(s) => s.parse().expect("invalid sample rate"),
+        None => 10000,
+    };
+
+    // The baud rate to use.
+    let br: usize = match opts.opt_str("b") {
+        Some(s) => s.parse().expect("invalid baud rate"),
+        None => meta.as_ref().and_then(|m| m.1.baud_rate).unwrap_or(220),
+    };
+
+    let cf: usize = match opts.opt_str("c") {
+        Some(f) => f.parse().expect("invalid carrier frequency"),
+        None => meta.as_ref().and_then(|m| m.1.frequency)
+                    .map_or(1000, |f| f.round() as usize),
+    };
+
+    assert!(cf > 0);
+    assert!(cf < sr / 2);
+
+    // Number of samples in the carrier sync tone, matching what modulate generates.
+    let preamble: usize = match opts.opt_str("p") {
+        Some(c) => {
+            carrier::preamble_len(sr, cf, c.parse().expect("invalid preamble cycles"))
+        },
+        // Convert the annotated extent from the recording's rate. modulate wrote it
+        // scaled from the same length, so at the modulated rate they agree.
+        None => meta.as_ref().and_then(|m| {
+            m.1.annotation("preamble").map(|a| a.count * sr / m.1.sample_rate)
+        }).unwrap_or(0),
+    };
+
+    let meta_pulse = meta.as_ref().and_then(|m| m.1.pulse.clone());
+
+    let rolloff: f32 = match opts.opt_str("rolloff") {
+        Some(r) => r.parse().expect("invalid roll-off factor"),
+        None => meta_pulse.as_ref().map_or(0.35, |p| p.1),
+    };
+
+    let span: usize = match opts.opt_str("span") {
+        Some(s) => s.parse().expect("invalid pulse span"),
+        None => meta_pulse.as_ref().map_or(8, |p| p.2),
+    };
+
+    // The input sample rate, if it differs from the one demodulated at.
+    let in_rate: Option<usize> = opts.opt_str("in-rate").map(|r| {
+        r.parse().expect("invalid input sample rate")
+    });
+
+    let pulse = opts.opt_str("pulse").or(meta_pulse.map(|p| p.0));
+
+    let pulse = pulse.map(|p| {
+        Pulse::parse(&p, rolloff).expect("invalid pulse shape")
+    });
+
+    let map_name = opts.opt_str("mapping").unwrap_or("natural".to_string());
+    assert!(digital::mapping::parse(&map_name).is_some(), "invalid mapping");
+
+    // Each phasor takes its own mapping.
+    let mapping = || digital::mapping::parse(&map_name).unwrap();
+
+    assert!(digital::mapping::fits_modulation(&mapping(), &dmod),
+            "mapping doesn't fit the modulation");
+
+    let rates = Rates::new(br, sr);
+    // Exact samples per symbol, so the filters line up with symbols that alternate
+    // between whole numbers of samples.
+    let sps = rates.symbol_period();
+    let half_symbol = (sps / 2.0) as usize;
+
+    // Parse the digital modulation into a slicer, the constellation for soft decisions
+    // if it gives them, and a carrier phase detector, which tracks the phase left after
+    // the preamble lock at each symbol. Without a preamble for the absolute carrier
+    // phase, BPSK/QPSK use a Costas loop.
+    let (slicer, mut soft, detector): Decisions = match dmod.as_ref() {
+        "bask" => decision(|| digital::bask::BASK::new(AMPLITUDE)),
+        "bpsk" => (Box::new(digital::bpsk::BPSK::new(PI / 4.0, AMPLITUDE)), None,
+                   Box::new(pll::Costas::new(1, PI / 4.0))),
+        "qpsk" => (Box::new(digital::qpsk::QPSK::new(0.0, AMPLITUDE)), None,
+                   Box::new(pll::Costas::new(2, PI / 4.0))),
+        _ if qam_bits.is_some() => soft_decision(|| digital::qam::QAM::with_mapping(
+            qam_bits.unwrap(), 0.0, AMPLITUDE, mapping())),
+        "16psk" => soft_decision(|| digital::mpsk::MPSK::with_mapping(4, 0.0, AMPLITUDE,
+                                                                       mapping())),
+        "16apsk" => soft_decision(|| digital::apsk::APSK::with_mapping(AMPLITUDE, 4, vec![
+            digital::apsk::Ring::new(0..4, 0.5, PI / 4.0),
+            digital::apsk::Ring::new(4..16, 1.0, PI / 12.0),
+        ], mapping())),
+        // Modulate offers these, but there's no receiver for them yet.
+        "bfsk" | "msk" | "mfsk" | "oqpsk" | "dcqpsk" | "16cpfsk" | "dqpsk" | "dbpsk" =>
+            panic!("{} can't be demodulated yet", dmod),
+        _ => panic!("invalid digital modulation"),
+    };
 
-    let mut hfir = hilbert();
-    let analytic = Box::new(input.map(move |x| {
-        num::Complex::new(x, hfir.add(x))
-    }));
+    // The baseband filter only has to reject the image at twice the carrier, so its
+    // cutoff sits midway at the carrier frequency, which keeps the passband flat across
+    // the symbol spectrum. Any cutoff near the baud rate would droop over a shaped
+    // pulse and skew both its gain and its response at the other symbol instants.
+    let lp_taps = fir::design::lowpass(Freq::new(cf, sr), odd(sr * 4 / cf),
+                                        Window::Hamming);
+    let lp_delay = lp_taps.len() / 2;
+
+    let hilbert_taps = fir::design::hilbert(HILBERT_TAPS, Window::Hamming);
+    let hilbert_delay = HILBERT_TAPS / 2;
+
+    // Delay the real part to line up with the Hilbert transform.
+    let mut delay_taps = vec![0.0; HILBERT_TAPS];
+    delay_taps[hilbert_delay] = 1.0;
+
+    // Receive filter for the transmit pulse, kept outside the carrier loop so its delay
+    // doesn't destabilize it. Each symbol peaks after the delay of the transmit and
+    // receive filters, rather than halfway through a rectangular symbol, and the
+    // transmitted tail of the shaping filter already flushes most of that delay.
+    let (receive_taps, peak_delay, tail) = match pulse {
+        Some(p) => {
+            let taps = p.receive(sps, span);
+            let delay = taps.len() - 1;
+
+            (taps, delay, delay)
+        },
+        None => (vec![1.0], half_symbol, 0),
+    };
+
+    // A WAV file carries its own sample rate, which overrides the given input rate.
+    let (input, in_rate): (Box<Iterator<Item = f32>>, Option<usize>) =
+        if opts.opt_present("wav") {
+            let wav = wav::WavReader::new(std::io::stdin()).expect("invalid WAV input");
+            let spec = wav.spec();
+
+            let input: Box<Iterator<Item = f32>> = match spec.channels {
+                1 => Box::new(wav),
+                2 => Box::new(Upconvert::new(wav, Freq::new(cf, spec.sample_rate))),
+                _ => panic!("WAV input must be mono or stereo IQ"),
+            };
+
+            (input, Some(spec.sample_rate))
+        } else {
+            // A SigMF recording also carries its own sample format.
+            let (stream, format, in_rate): (Box<Read>, raw::Format, Option<usize>) =
+                match meta {
+                    Some((base, m)) => {
+                        let f = File::open(format!("{}.sigmf-data", base))
+                                    .expect("can't open SigMF data");
+
+                        (Box::new(BufReader::new(f)), m.datatype, Some(m.sample_rate))
+                    },
+                    None => {
+                        let format = opts.opt_str("format").map_or(
+                            raw::Format::parse("f32").unwrap(),
+                            |f| raw::Format::parse(&f).expect("invalid sample format"));
+
+                        (Box::new(std::io::stdin()), format, in_rate)
+                    },
+                };
+
+            let samples = raw::Reader::new(stream, format);
+
+            let input: Box<Iterator<Item = f32>> = if format.complex {
+                Box::new(Upconvert::new(samples, Freq::new(cf, in_rate.unwrap_or(sr))))
+            } else {
+                Box::new(samples)
+            };
+
+            (input, in_rate)
+        };
+
+    let input: Box<Iterator<Item = f32>> = match in_rate {
+        Some(rate) if rate != sr => {
+            Box::new(Resampler::new(input, rate, sr, TAPS_PER_PHASE))
+        },
+        _ => input,
+    };
+
+    // Pad the end so the filters flush out the final symbol.
+    let flush = hilbert_delay + lp_delay + peak_delay - tail;
+    let input = input.chain(std::iter::repeat(0.0).take(flush));
+
+    let mut hfir = fir::FIRFilter::new(&hilbert_taps[..]);
+    let mut dfir = fir::FIRFilter::new(&delay_taps[..]);
+
+    // Skip the Hilbert delay so the local carrier starts in phase with the input.
+    let analytic = input.map(move |x| {
+        num::Complex::new(dfir.add(x), hfir.add(x))
+    }).skip(hilbert_delay);
 
-    let carrier_freq = freq::Freq::new(900, SAMPLE_RATE);
     let mut demod = demodulator::Demodulator::new(
-        carrier::Carrier::new(carrier_freq), analytic, lowpass);
-    demod.lock_phase();
+        Carrier::new(Freq::new(cf, sr)), analytic,
+        || fir::FIRFilter::new(&lp_taps[..]),
+        pll::PLL::new(LOOP_BANDWIDTH, LOOP_DAMPING));
+
+    if !demod.lock_phase(preamble) {
+        println!("no signal");
+        return;
+    }
+
+    let mut receive = fir::FIRFilter::new(receive_taps);
+
+    let baseband = demod.map(|(i, q)| {
+        let y = receive.add(num::Complex::new(i, q));
+        (y.re, y.im)
+    });
+
+    // Skip over the filter delays so the symbol clock starts on a symbol boundary.
+    let baseband = baseband.skip(lp_delay + peak_delay - half_symbol);
 
-    for (i, q) in demod {
-        println!("i:{}\tq:{}", i, q);
+    let symbols = timing::TimingRecovery::new(baseband, rates, timing::Detector::Gardner,
+                                              TIMING_BANDWIDTH);
+
+    let symbols = demodulator::Tracker::new(symbols, detector,
+        pll::PLL::new(TRACKING_BANDWIDTH, LOOP_DAMPING));
+
+    let mut descrambler = opts.opt_str("scramble").map(|s| {
+        Scrambler::preset(&s).expect("invalid scrambler")
+    });
+
+    // Check the bits rather than output them.
+    let mut checker = opts.opt_str("ber").map(|p| {
+        prbs::Checker::new(prbs::Pattern::parse(&p).expect("invalid test pattern"))
+    });
+
+    // Decode the soft decisions if the constellation gives them, and otherwise the hard
+    // decisions from the slicer.
+    let fec = conv::parse_options(opts.opt_str("code").as_ref().map(|c| &c[..]),
+                                  opts.opt_str("fec").as_ref().map(|r| &r[..]));
+
+    let mut decoder = fec.map(|(code, puncture)| {
+        conv::Viterbi::punctured(code, puncture)
+    });
+
+    let bps = slicer.bits_per_symbol();
+    let mut bits = vec![0; bps];
+    let mut llrs = vec![0.0; bps];
+    let mut noise = NoiseEstimate::new(NOISE_SYMBOLS);
+    let mut decoded = vec![];
+    let mut line = String::new();
+
+    for (i, q) in symbols {
+        slicer.slice(i, q, &mut bits[..]);
+
+        decoded.clear();
+
+        match (decoder.as_mut(), soft.as_mut()) {
+            (Some(d), Some(c)) => {
+                // Measure the noise against the point of the hard decision.
+                let (di, dq) = c.next(0, &bits[..]);
+                noise.add(i - di, q - dq);
+
+                c.llr(i, q, noise.var(), Metric::MaxLog, &mut llrs[..]);
+
+                for &l in llrs.iter() {
+                    d.add_llr(l, &mut decoded);
+                }
+            },
+            (Some(d), None) => for &b in bits.iter() {
+                d.add_bit(b, &mut decoded);
+            },
+            (None, _) => decoded.extend_from_slice(&bits[..]),
+        }
+
+        for &b in decoded.iter() {
+            output(b, &mut descrambler, &mut checker, &mut line, bps);
+        }
+    }
+
+    if let Some(ref mut d) = decoder {
+        decoded.clear();
+        d.finish(&mut decoded);
+
+        for &b in decoded.iter() {
+            output(b, &mut descrambler, &mut checker, &mut line, bps);
+        }
+    }
+
+    if !line.is_empty() {
+        println!("{}", line);
+    }
+
+    if let Some(c) = checker {
+        if !c.locked() && c.bits() == 0 {
+            println!("no lock");
+            return;
+        }
+
+        let (lo, hi) = c.interval(0.95);
+
+        println!("bits {}, errors {}, slips {}, locked {}", c.bits(), c.errors(),
+                 c.slips(), c.locked());
+        println!("BER {:.3e} (95% confidence {:.3e} to {:.3e})", c.ber(), lo, hi);
     }
 }
 
-// Create a Hilbert transform FIR filter. Generated with matlab.
-pub fn hilbert() -> fir::FIRFilter<'static> {
-    const COEFS: &'static [f32] = &[
-        -0.007576,
-        -2.803e-16,
-        -0.019824,
-        3.7096e-16,
-        -0.044089,
-        1.3201e-16,
-        -0.089244,
-        -3.2694e-16,
-        -0.18728,
-        -1.6739e-16,
-        -0.62794,
-        0.0,
-        0.62794,
-        1.6739e-16,
-        0.18728,
-        3.2694e-16,
-        0.089244,
-        -1.3201e-16,
-        0.044089,
-        -3.7096e-16,
-        0.019824,
-        2.803e-16,
-        0.007576,
-    ];
-
-    fir::FIRFilter::new(COEFS)
+// Descramble a received bit, then check it or print it in lines of the given length.
+fn output(bit: u8, descrambler: &mut Option<Scrambler>,
+          checker: &mut Option<prbs::Checker>, line: &mut String, len: usize)
+{
+    let bit = match *descrambler {
+        Some(ref mut d) => d.descramble(bit),
+        None => bit,
+    };
+
+    if let Some(ref mut c) = *checker {
+        c.add(bit);
+        return;
+    }
+
+    line.push((b'0' + bit) as char);
+
+    if line.len() == len {
+        println!("{}", line);
+        line.clear();
+    }
+}
+
+// A constellation that gives soft decisions, and the points of its hard decisions.
+trait Constellation: DigitalPhasor + SoftSlicer {}
+
+impl<P: DigitalPhasor + SoftSlicer> Constellation for P {}
+
+// Slicer, constellation for soft decisions if there is one, and carrier phase detector.
+type Decisions = (Box<Slicer>, Option<Box<Constellation>>, Box<pll::PhaseDetector>);
+
+// Create a slicer and decision-directed detector for the given constellation, without
+// soft decisions.
+fn decision<P, F>(phasor: F) -> Decisions
+    where P: DigitalPhasor + Slicer + 'static, F: Fn() -> P
+{
+    (Box::new(phasor()), None, Box::new(pll::DecisionDirected::new(phasor())))
+}
+
+// Create a slicer, soft decisions, and decision-directed detector for the given
+// constellation.
+fn soft_decision<P, F>(phasor: F) -> Decisions
+    where P: DigitalPhasor + SoftSlicer + 'static, F: Fn() -> P
+{
+    (Box::new(phasor()), Some(Box::new(phasor())),
+     Box::new(pll::DecisionDirected::new(phasor())))
 }
 
-// Create a lowpass FIR filter with
-//   passband: 0 to 1000Hz
-//   stopband: 1500 to 5000Hz
-// Assuming a 10,000Hz sample rate. Generated with matlab.
-pub fn lowpass() -> fir::FIRFilter<'static> {
-    const COEFS: &'static [f32] = &[
-        8.6464950643449706e-05,
-        -0.0011227727551926443,
-        -0.0010137373532784653,
-        -0.00051892546397063074,
-        0.00065737693207229997,
-        0.0019426724039296576,
-        0.0023575316971358984,
-        0.0011698129325984573,
-        -0.0014109570575621668,
-        -0.0040119731215088154,
-        -0.0047065995954001117,
-        -0.0022692944513388992,
-        0.0026579628895631122,
-        0.0073998732470493874,
-        0.0085194671337849165,
-        0.0040456650224074651,
-        -0.0046645972566385554,
-        -0.012862659808170144,
-        -0.014703261637603555,
-        -0.0069572953029268525,
-        0.00800563700908981,
-        0.022172065878291854,
-        0.025574286331781385,
-        0.012291851983914071,
-        -0.014450589851381347,
-        -0.041421606566596714,
-        -0.05018918856526014,
-        -0.025933101216317672,
-        0.03394517722329659,
-        0.11612232604813434,
-        0.19513123601730936,
-        0.24347923270043995,
-        0.24347923270043995,
-        0.19513123601730936,
-        0.11612232604813434,
-        0.03394517722329659,
-        -0.025933101216317672,
-        -0.05018918856526014,
-        -0.041421606566596714,
-        -0.014450589851381347,
-        0.012291851983914071,
-        0.025574286331781385,
-        0.022172065878291854,
-        0.00800563700908981,
-        -0.0069572953029268525,
-        -0.014703261637603555,
-        -0.012862659808170144,
-        -0.0046645972566385554,
-        0.0040456650224074651,
-        0.0085194671337849165,
-        0.0073998732470493874,
-        0.0026579628895631122,
-        -0.0022692944513388992,
-        -0.0047065995954001117,
-        -0.0040119731215088154,
-        -0.0014109570575621668,
-        0.0011698129325984573,
-        0.0023575316971358984,
-        0.0019426724039296576,
-        0.00065737693207229997,
-        -0.00051892546397063074,
-        -0.0010137373532784653,
-        -0.0011227727551926443,
-        8.6464950643449706e-05,
-    ];
-
-    fir::FIRFilter::new(COEFS)
+// Round up to an odd number of taps.
+fn odd(taps: usize) -> usize {
+    taps | 1
+}
+
+// Modulate interleaved baseband IQ samples onto the carrier, as output by modulate, so
+// they can be demodulated like a real waveform.
+struct Upconvert<I: Iterator<Item = f32>> {
+    samples: I,
+    nco: NCO,
+}
+
+impl<I: Iterator<Item = f32>> Upconvert<I> {
+    fn new(samples: I, carrier: Freq) -> Upconvert<I> {
+        Upconvert {
+            samples: samples,
+            nco: NCO::new(carrier),
+        }
+    }
+}
+
+impl<I: Iterator<Item = f32>> Iterator for Upconvert<I> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (i, q) = match (self.samples.next(), self.samples.next()) {
+            (Some(i), Some(q)) => (i, q),
+            _ => return None,
+        };
+
+        let (sin, cos) = self.nco.sin_cos();
+        self.nco.next();
+
+        Some(i * cos - q * sin)
+    }
 }
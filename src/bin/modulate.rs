@@ -1,14 +1,17 @@
-extern crate byteorder;
 extern crate getopts;
 extern crate modem;
 
-use byteorder::{LittleEndian, WriteBytesExt};
 use std::f32::consts::PI;
+use std::fs::File;
+use std::io::{BufWriter, Write};
 
-use modem::{phasor, modulator, digital, data};
+use modem::{phasor, modulator, conv, digital, data, prbs, raw, sigmf, wav};
+use modem::scramble::{Scrambler, Scrambled};
 use modem::freq::Freq;
 use modem::rates::Rates;
-use modem::carrier::Carrier;
+use modem::carrier::{self, Carrier};
+use modem::pulse::Pulse;
+use modem::resample::{Resampler, TAPS_PER_PHASE};
 
 // The maximum amplitude of the output waveform.
 const AMPLITUDE: f32 = 1.0;
@@ -18,7 +21,6 @@ const USAGE: &'static str = "
     Modulate the bits on stdin to a waveform on stdout";
 
 fn main() {
-    let mut out = std::io::stdout();
     let mut parser = getopts::Options::new();
 
     parser.optflag("h", "help", "show usage")
@@ -27,7 +29,28 @@ fn main() {
           .optopt("b", "", "baud rate (symbols/sec)", "RATE")
           .optopt("c", "", "carrier frequency (Hz)", "FREQ")
           .optopt("p", "", "preamble cycles", "CYCLES")
-          .optflag("", "iq", "output raw IQ samples");
+          .optopt("", "offset", "carrier frequency offset for testing (Hz)", "FREQ")
+          .optopt("", "table", "look up the carrier in a sine table of the given size, \
+                                a power of two", "SIZE")
+          .optopt("", "mapping", "constellation labelling (natural, gray, dvbs2)", "MAP")
+          .optopt("", "pulse", "pulse shape (rrc, rc, gaussian, halfsine)", "SHAPE")
+          .optopt("", "rolloff", "pulse roll-off factor, or BT for gaussian", "FACTOR")
+          .optopt("", "span", "pulse length (symbols)", "SYMBOLS")
+          .optopt("", "out-rate", "resample the output to the given rate", "RATE")
+          .optflagopt("", "bytes", "read raw bytes rather than ASCII bits, sent MSB or \
+                                    LSB first", "ORDER")
+          .optopt("", "prbs", "send a test pattern (prbs7, prbs9, prbs15, prbs23, \
+                               prbs31) rather than stdin", "PATTERN")
+          .optopt("", "prbs-bits", "number of test pattern bits to send", "BITS")
+          .optopt("", "scramble", "scramble the bits (v22, v32, 80211, dvbs)", "NAME")
+          .optopt("", "fec", "convolutional code rate (1/2, 2/3, 3/4, 5/6, 7/8)", "RATE")
+          .optopt("", "code", "convolutional code as K:GEN,GEN,... in octal, by default \
+                               7:171,133", "CODE")
+          .optflag("", "iq", "output IQ samples")
+          .optopt("", "format", "raw sample format (f32, s16be, cs16, cu8, ...)", "FMT")
+          .optflagopt("", "wav", "output WAV (pcm8, pcm16, pcm24, pcm32, float)", "FMT")
+          .optopt("", "sigmf", "output a SigMF recording, BASE.sigmf-meta and -data",
+                  "BASE");
 
     let args: Vec<_> = std::env::args().skip(1).collect();
     let opts = parser.parse(&args).unwrap();
@@ -40,6 +63,9 @@ fn main() {
     // The digital modulation to use.
     let dmod = opts.opt_str("m").expect("digital modulation is required");
 
+    // The bits per symbol, if the modulation is QAM.
+    let qam_bits = digital::qam::parse_bits(&dmod);
+
     // The sample rate to use.
     let sr: usize = match opts.opt_str("r") {
         Some(s) => s.parse().expect("invalid sample rate"),
@@ -57,18 +83,76 @@ fn main() {
         None => 1000,
     };
 
+    // Offset the carrier from what the demodulator expects, to test its tracking.
+    let offset: f64 = match opts.opt_str("offset") {
+        Some(f) => f.parse().expect("invalid carrier offset"),
+        None => 0.0,
+    };
+
     let pc: usize = match opts.opt_str("p") {
-        Some(c) => {
-            assert!(sr % cf == 0);
-            c.parse().expect("invalid preamble cycles")
-        },
+        Some(c) => c.parse().expect("invalid preamble cycles"),
         None => 0,
     };
 
+    let rolloff: f32 = match opts.opt_str("rolloff") {
+        Some(r) => r.parse().expect("invalid roll-off factor"),
+        None => 0.35,
+    };
+
+    let span: usize = match opts.opt_str("span") {
+        Some(s) => s.parse().expect("invalid pulse span"),
+        None => 8,
+    };
+
+    // The output sample rate, if it differs from the one modulated at.
+    let out_rate: Option<usize> = opts.opt_str("out-rate").map(|r| {
+        r.parse().expect("invalid output sample rate")
+    });
+
+    let format = opts.opt_default("wav", "float").map(|f| match f.as_ref() {
+        "pcm8" => wav::Format::PCM8,
+        "pcm16" => wav::Format::PCM16,
+        "pcm24" => wav::Format::PCM24,
+        "pcm32" => wav::Format::PCM32,
+        "float" => wav::Format::Float32,
+        _ => panic!("invalid WAV format"),
+    });
+
+    let raw_format = opts.opt_str("format").map(|f| {
+        raw::Format::parse(&f).expect("invalid sample format")
+    });
+
+    assert!(format.is_none() || raw_format.is_none(), "can't output both WAV and raw");
+
+    // Write raw samples to a data file alongside their metadata rather than to stdout.
+    let sigmf = opts.opt_str("sigmf");
+    assert!(format.is_none() || sigmf.is_none(), "SigMF recordings hold raw samples");
+
+    let pulse = opts.opt_str("pulse").map(|p| {
+        Pulse::parse(&p, rolloff).expect("invalid pulse shape")
+    });
+
+    let map_name = opts.opt_str("mapping").unwrap_or("natural".to_string());
+    assert!(digital::mapping::parse(&map_name).is_some(), "invalid mapping");
+
+    // Each phasor takes its own mapping.
+    let mapping = || digital::mapping::parse(&map_name).unwrap();
+
+    assert!(digital::mapping::fits_modulation(&mapping(), &dmod),
+            "mapping doesn't fit the modulation");
+
+    assert!(cf > 0);
     assert!(cf < sr / 2);
 
     let rates = Rates::new(br, sr);
-    let mut carrier = Carrier::new(Freq::new(cf, sr));
+    let mut carrier = Carrier::new(Freq::new(cf, sr).offset(offset));
+
+    if let Some(t) = opts.opt_str("table") {
+        let size: usize = t.parse().expect("invalid table size");
+        assert!(size.is_power_of_two(), "table size must be a power of two");
+
+        carrier.nco.set_table(size);
+    }
 
     // Parse the digital modulation into a phasor.
     let phasor: Box<digital::DigitalPhasor> = match dmod.as_ref() {
@@ -76,59 +160,236 @@ fn main() {
         "bpsk" => Box::new(digital::bpsk::BPSK::new(PI / 4.0, AMPLITUDE)),
         "bfsk" => Box::new(digital::bfsk::BFSK::new(Freq::new(200, sr), AMPLITUDE)),
         "qpsk" => Box::new(digital::qpsk::QPSK::new(0.0, AMPLITUDE)),
-        "qam16" => Box::new(digital::qam::QAM::new(4, 0.0, AMPLITUDE)),
-        "qam256" => Box::new(digital::qam::QAM::new(8, 0.0, AMPLITUDE)),
-        "msk" => Box::new(digital::msk::MSK::new(AMPLITUDE, rates.samples_per_symbol)),
+        _ if qam_bits.is_some() => Box::new(digital::qam::QAM::with_mapping(
+            qam_bits.unwrap(), 0.0, AMPLITUDE, mapping())),
+        "msk" => Box::new(digital::msk::MSK::new(AMPLITUDE, rates.symbol_period())),
         "mfsk" => Box::new(digital::mfsk::MFSK::new(4, Freq::new(50, sr),
             AMPLITUDE, digital::mfsk::IncreaseMap)),
-        "16psk" => Box::new(digital::mpsk::MPSK::new(4, 0.0, AMPLITUDE)),
+        "16psk" => Box::new(digital::mpsk::MPSK::with_mapping(4, 0.0, AMPLITUDE,
+                                                               mapping())),
         "oqpsk" => Box::new(digital::oqpsk::OQPSK::new(AMPLITUDE)),
         "dcqpsk" => Box::new(digital::dcqpsk::DCQPSK::new(AMPLITUDE)),
         "16cpfsk" => Box::new(digital::cpfsk::CPFSK::new(4, rates, AMPLITUDE, 1)),
-        "16apsk" => Box::new(digital::apsk::APSK::new(AMPLITUDE, 4, vec![
+        "16apsk" => Box::new(digital::apsk::APSK::with_mapping(AMPLITUDE, 4, vec![
             digital::apsk::Ring::new(0..4, 0.5, PI / 4.0),
             digital::apsk::Ring::new(4..16, 1.0, PI / 12.0),
-        ])),
+        ], mapping())),
         "dqpsk" => Box::new(digital::dmpsk::DMPSK::new(2, AMPLITUDE, PI / 4.0, PI / 2.0)),
         "dbpsk" => Box::new(digital::dmpsk::DMPSK::new(1, AMPLITUDE, PI / 4.0, PI)),
         _ => panic!("invalid digital modulation"),
     };
 
-    // Get the user-supplied bits.
-    let bits = data::AsciiBits::new(std::io::stdin(), rates.samples_per_symbol,
-                                    phasor.bits_per_symbol());
+    // Shaping filters an impulse per symbol, so the phasor must hold a constant (i, q)
+    // over each symbol.
+    if pulse.is_some() {
+        match dmod.as_ref() {
+            "bask" | "bpsk" | "qpsk" | "16psk" | "16apsk" | "dqpsk" | "dbpsk" => {},
+            _ if qam_bits.is_some() => {},
+            _ => panic!("pulse shaping requires a linear modulation"),
+        }
+    }
+
+    let taps = match pulse {
+        Some(p) => p.shaping(rates.symbol_period(), span),
+        None => vec![],
+    };
+
+    let bps = phasor.bits_per_symbol();
+
+    let pattern = opts.opt_str("prbs").map(|p| {
+        prbs::Pattern::parse(&p).expect("invalid test pattern")
+    });
+
+    let prbs_bits: usize = match opts.opt_str("prbs-bits") {
+        Some(b) => b.parse().expect("invalid test pattern length"),
+        None => 10000,
+    };
+
+    // Get the user-supplied bits, padding out the final symbol of a byte stream.
+    let bits: Box<data::Source> = match (pattern, opts.opt_default("bytes", "msb")) {
+        (Some(p), _) => Box::new(data::Prbs::new(p, rates, bps, prbs_bits)),
+        (None, Some(order)) => {
+            let order = match order.as_ref() {
+                "msb" => data::BitOrder::MsbFirst,
+                "lsb" => data::BitOrder::LsbFirst,
+                _ => panic!("invalid bit order"),
+            };
+
+            Box::new(data::Bytes::new(std::io::stdin(), rates, bps, order,
+                                      data::Padding::Zeros))
+        },
+        (None, None) => Box::new(data::AsciiBits::new(std::io::stdin(), rates, bps)),
+    };
+
+    let bits: Box<data::Source> = match opts.opt_str("scramble") {
+        Some(s) => Box::new(Scrambled::new(bits, Scrambler::preset(&s)
+                                                      .expect("invalid scrambler"))),
+        None => bits,
+    };
+
+    // Encode after scrambling, so the receiver descrambles the decoded bits.
+    let fec = conv::parse_options(opts.opt_str("code").as_ref().map(|c| &c[..]),
+                                  opts.opt_str("fec").as_ref().map(|r| &r[..]));
+
+    let bits: Box<data::Source> = match fec {
+        Some((code, puncture)) => {
+            Box::new(data::Encoded::new(bits, conv::Encoder::punctured(code, puncture),
+                                        rates, bps))
+        },
+        None => bits,
+    };
 
     let src: Box<data::Source> = match dmod.as_ref() {
         // MSK and OQPSK require an offset bit source
         "msk" | "oqpsk" =>
-            Box::new(data::EvenOddOffset::new(bits, rates.samples_per_symbol,
-                phasor.bits_per_symbol())),
-        _ => Box::new(bits),
+            Box::new(data::EvenOddOffset::new(bits, rates, bps)),
+        _ => bits,
     };
 
-    if opts.opt_present("iq") {
-        for s in modulator::DigitalModulator::new(&mut carrier, phasor, src) {
-            out.write_f32::<LittleEndian>(s.i).unwrap();
-            out.write_f32::<LittleEndian>(s.q).unwrap();
+    // A complex sample format implies IQ output.
+    let iq = opts.opt_present("iq") || raw_format.map_or(false, |f| f.complex);
+
+    let mut sample_format = raw_format.unwrap_or(raw::Format::parse("f32").unwrap());
+    sample_format.complex = iq;
+
+    let meta = sigmf.as_ref().map(|_| {
+        let mut m = sigmf::Meta::new(sample_format, out_rate.unwrap_or(sr));
+
+        m.frequency = Some(cf as f64);
+        m.modulation = Some(dmod.clone());
+        m.baud_rate = Some(br);
+        m.pulse = opts.opt_str("pulse").map(|p| (p, rolloff, span));
+
+        m
+    });
+
+    let mut out = match format {
+        Some(f) => {
+            let channels = if iq { 2 } else { 1 };
+            let spec = wav::Spec::new(f, channels, out_rate.unwrap_or(sr));
+
+            Output::Wav(wav::WavWriter::new(std::io::Cursor::new(vec![]), spec).unwrap())
+        },
+        None => {
+            let stream: Box<Write> = match sigmf {
+                Some(ref base) => Box::new(BufWriter::new(
+                    File::create(format!("{}.sigmf-data", base))
+                        .expect("can't create SigMF data"))),
+                None => Box::new(std::io::stdout()),
+            };
+
+            Output::Raw(raw::Writer::new(stream, sample_format))
+        },
+    };
+
+    if iq {
+        assert!(out_rate.is_none(), "IQ output can't be resampled");
+
+        let mut count = 0;
+
+        for s in digital_modulator(&mut carrier, phasor, src, &taps[..]) {
+            out.write(s.i);
+            out.write(s.q);
+            count += 1;
+        }
+
+        out.finish();
+
+        if let (Some(base), Some(meta)) = (sigmf, meta) {
+            write_meta(&base, meta, 0, count);
         }
 
         return;
     }
 
-    if pc > 0 {
-        // Generate the initial carrier sync tone.
-        let preamble = modulator::Modulator::new(&mut carrier,
-            Box::new(phasor::Raw::new(AMPLITUDE)));
+    // Generate the initial carrier sync tone.
+    let preamble: Vec<f32> = if pc > 0 {
+        modulator::Modulator::new(&mut carrier, Box::new(phasor::Raw::new(AMPLITUDE)))
+            .map(|x| x.modulate().re).take(carrier::preamble_len(sr, cf, pc)).collect()
+    } else {
+        vec![]
+    };
+
+    let digi = digital_modulator(&mut carrier, phasor, src, &taps[..])
+                   .map(|x| x.modulate().re);
+
+    // Length of the preamble at the output rate, which demodulate scales back to its own
+    // rate to get the same length as from the cycles.
+    let preamble_len = preamble.len() * out_rate.unwrap_or(sr) / sr;
+
+    let samples = preamble.into_iter().chain(digi);
+    let mut count = 0;
+
+    match out_rate {
+        Some(rate) => {
+            for s in Resampler::new(samples, sr, rate, TAPS_PER_PHASE) {
+                out.write(s);
+                count += 1;
+            }
+        },
+        None => for s in samples {
+            out.write(s);
+            count += 1;
+        },
+    }
+
+    out.finish();
+
+    if let (Some(base), Some(meta)) = (sigmf, meta) {
+        write_meta(&base, meta, preamble_len, count);
+    }
+}
+
+// Write the SigMF metadata for a recording of the given number of samples, annotating
+// the preamble at the start and the payload after it.
+fn write_meta(base: &str, mut meta: sigmf::Meta, preamble: usize, count: usize) {
+    if preamble > 0 {
+        meta.annotations.push(sigmf::Annotation::new(0, preamble, "preamble"));
+    }
+
+    meta.annotations.push(sigmf::Annotation::new(preamble, count - preamble, "payload"));
 
-        for s in preamble.map(|x| x.modulate().re).take(sr / cf * pc - 1) {
-            out.write_f32::<LittleEndian>(s).unwrap();
+    let f = File::create(format!("{}.sigmf-meta", base))
+                .expect("can't create SigMF metadata");
+    meta.write(f).unwrap();
+}
+
+// Sample output, either a raw stream or a WAV file. Stdout can't seek back to
+// fill in the WAV header sizes, so the file is built in memory.
+enum Output {
+    Raw(raw::Writer<Box<Write>>),
+    Wav(wav::WavWriter<std::io::Cursor<Vec<u8>>>),
+}
+
+impl Output {
+    fn write(&mut self, x: f32) {
+        match *self {
+            Output::Raw(ref mut w) => w.write(x).unwrap(),
+            Output::Wav(ref mut w) => w.write(x).unwrap(),
         }
     }
 
-    let digi = modulator::DigitalModulator::new(&mut carrier, phasor, src)
-                   .map(|x| x.modulate().re);
+    fn finish(self) {
+        match self {
+            Output::Raw(w) => w.into_inner().flush().unwrap(),
+            Output::Wav(w) => {
+                let buf = w.finish().unwrap().into_inner();
+                std::io::stdout().write_all(&buf[..]).unwrap();
+            },
+        }
+    }
+}
 
-    for s in digi {
-        out.write_f32::<LittleEndian>(s).unwrap();
+// Create a digital modulator, shaped by the given taps if there are any.
+fn digital_modulator<'a>(carrier: &'a mut Carrier, phasor: Box<digital::DigitalPhasor>,
+                         src: Box<data::Source>, taps: &'a [f32])
+    -> modulator::DigitalModulator<'a>
+{
+    let mut m = modulator::DigitalModulator::new(carrier, phasor, src);
+
+    if !taps.is_empty() {
+        m.set_pulse(taps);
     }
+
+    m
 }
@@ -1,27 +1,56 @@
-use super::{util, freq};
+use super::freq;
+use super::nco::NCO;
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Carrier {
-    sample_freq: f32,
+    // Oscillator generating the carrier phase, which can be retuned mid-stream.
+    pub nco: NCO,
     pub sample: usize,
 }
 
+/// Get the number of samples in a preamble of the given number of cycles of unmodulated
+/// carrier, at the given sample rate, which must be a multiple of the carrier frequency.
+/// The preamble stops a sample short of its last cycle, and is empty for no cycles.
+pub fn preamble_len(sample_rate: usize, freq: usize, cycles: usize) -> usize {
+    if cycles == 0 {
+        return 0;
+    }
+
+    assert!(sample_rate % freq == 0);
+    sample_rate / freq * cycles - 1
+}
+
 impl Carrier {
     pub fn new(freq: freq::Freq) -> Carrier {
         Carrier {
-            sample_freq: freq.sample_freq(),
+            nco: NCO::new(freq),
             sample: 0,
         }
     }
 
-    fn inner(&self, s: usize) -> f32 {
-        util::mod_trig(self.sample_freq * s as f32)
-    }
-
     pub fn next(&mut self) -> f32 {
-        let sample = self.sample;
         self.sample += 1;
+        self.nco.next()
+    }
+
+    /// Get the sine and cosine of the current phase, looked up in the oscillator's table
+    /// if it has one, and advance to the next sample.
+    pub fn next_sin_cos(&mut self) -> (f32, f32) {
+        let sin_cos = self.nco.sin_cos();
+        self.next();
+
+        sin_cos
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::preamble_len;
 
-        self.inner(sample)
+    #[test]
+    fn test_preamble_len() {
+        assert_eq!(preamble_len(10000, 1000, 20), 199);
+        assert_eq!(preamble_len(10000, 1000, 0), 0);
+        assert_eq!(preamble_len(44100, 1300, 0), 0);
     }
 }
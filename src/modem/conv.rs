@@ -0,0 +1,511 @@
+/// Implements rate 1/n convolutional codes, with puncturing to higher rates, and a
+/// Viterbi decoder taking hard bits or soft LLRs.
+///
+/// Generators are given in the usual octal form, with the MSB tapping the current input
+/// bit and the LSB the oldest, so the NASA standard K=7 code is `[0o171, 0o133]`. The
+/// encoder starts in the all-zeros state and is terminated with K - 1 zero tail bits,
+/// which the decoder relies on to pick the final path. Punctured bits are fed to the
+/// decoder as erasures, with an LLR of zero that favours neither bit.
+
+use std;
+use std::collections::VecDeque;
+
+/// Depth of the decoder traceback, in constraint lengths. Punctured codes need longer
+/// than the usual 5 for the surviving paths to merge.
+const DEPTH_PER_CONSTRAINT: usize = 12;
+
+#[derive(Clone, Debug)]
+pub struct Code {
+    constraint: usize,
+    generators: Vec<u32>,
+}
+
+impl Code {
+    /// Create a code with the given constraint length, from 2 to 16, and a generator
+    /// polynomial for each output bit.
+    pub fn new(constraint: usize, generators: Vec<u32>) -> Code {
+        assert!(constraint >= 2 && constraint <= 16);
+        assert!(!generators.is_empty());
+
+        for &g in &generators {
+            assert!(g != 0 && g < 1 << constraint);
+        }
+
+        Code {
+            constraint: constraint,
+            generators: generators,
+        }
+    }
+
+    /// K=7 rate 1/2 code with generators 171 and 133, used by 802.11, DVB-S, and
+    /// many others.
+    pub fn k7() -> Code {
+        Code::new(7, vec![0o171, 0o133])
+    }
+
+    /// Parse a code given as the constraint length and octal generators, such as
+    /// `7:171,133`.
+    pub fn parse(desc: &str) -> Option<Code> {
+        let mut parts = desc.splitn(2, ':');
+
+        let constraint: usize = match parts.next().map(|k| k.parse()) {
+            Some(Ok(k)) if k >= 2 && k <= 16 => k,
+            _ => return None,
+        };
+
+        let mut generators = vec![];
+
+        for g in parts.next().unwrap_or("").split(',') {
+            match u32::from_str_radix(g, 8) {
+                Ok(g) if g != 0 && g < 1 << constraint => generators.push(g),
+                _ => return None,
+            }
+        }
+
+        Some(Code::new(constraint, generators))
+    }
+
+    pub fn constraint(&self) -> usize {
+        self.constraint
+    }
+
+    /// Number of coded bits per input bit.
+    pub fn outputs(&self) -> usize {
+        self.generators.len()
+    }
+
+    /// Number of states, one for each value of the previous K - 1 bits.
+    fn states(&self) -> usize {
+        1 << (self.constraint - 1)
+    }
+
+    /// Compute the coded bits, one per bit of the result with the first generator in the
+    /// LSB, for the given input bit leaving the given state.
+    fn branch(&self, state: usize, bit: u8) -> u32 {
+        let reg = (bit as u32) << (self.constraint - 1) | state as u32;
+
+        self.generators.iter().enumerate().fold(0, |out, (i, &g)| {
+            out | ((reg & g).count_ones() & 1) << i
+        })
+    }
+
+    /// Get the state after the given input bit leaves the given state.
+    fn next_state(&self, state: usize, bit: u8) -> usize {
+        ((bit as usize) << (self.constraint - 1) | state) >> 1
+    }
+}
+
+/// Pattern of coded bits to send, repeating over a period of input bits.
+#[derive(Clone, Debug)]
+pub struct Puncture {
+    /// Whether each coded bit over a period is sent, in the order the encoder outputs
+    /// them.
+    mask: Vec<bool>,
+    outputs: usize,
+}
+
+impl Puncture {
+    /// Create a pattern from a row of 1s, for bits sent, and 0s, for bits deleted, for
+    /// each generator. The rows must all cover the same number of input bits.
+    pub fn new(rows: &[&[u8]]) -> Puncture {
+        assert!(!rows.is_empty() && !rows[0].is_empty());
+
+        let period = rows[0].len();
+        let mut mask = vec![];
+
+        for bit in 0..period {
+            for row in rows {
+                assert!(row.len() == period);
+                mask.push(row[bit] == 1);
+            }
+        }
+
+        assert!(mask.iter().any(|&m| m));
+
+        Puncture {
+            mask: mask,
+            outputs: rows.len(),
+        }
+    }
+
+    /// Send every bit of a code with the given number of outputs.
+    pub fn none(outputs: usize) -> Puncture {
+        Puncture {
+            mask: vec![true; outputs],
+            outputs: outputs,
+        }
+    }
+
+    /// Look up the standard pattern for a rate 1/2 code by its rate: `1/2`, `2/3`,
+    /// `3/4`, `5/6`, or `7/8`, as used by DVB-S.
+    pub fn rate(name: &str) -> Option<Puncture> {
+        match name {
+            "1/2" => Some(Puncture::none(2)),
+            "2/3" => Some(Puncture::new(&[&[1, 0], &[1, 1]])),
+            "3/4" => Some(Puncture::new(&[&[1, 0, 1], &[1, 1, 0]])),
+            "5/6" => Some(Puncture::new(&[&[1, 0, 1, 0, 1], &[1, 1, 0, 1, 0]])),
+            "7/8" => Some(Puncture::new(&[&[1, 0, 0, 0, 1, 0, 1],
+                                          &[1, 1, 1, 1, 0, 1, 0]])),
+            _ => None,
+        }
+    }
+
+    /// Get the code rate as the number of input bits and coded bits sent over a period.
+    pub fn ratio(&self) -> (usize, usize) {
+        (self.mask.len() / self.outputs, self.mask.iter().filter(|&&m| m).count())
+    }
+
+    /// Whether the coded bit at the given position in the encoder output is sent.
+    fn keep(&self, pos: usize) -> bool {
+        self.mask[pos % self.mask.len()]
+    }
+}
+
+/// Parse the code and puncturing given as options: a code description for
+/// `Code::parse`, defaulting to `Code::k7`, and a code rate for `Puncture::rate`, which
+/// needs a rate 1/2 code. Returns `None` for no coding if neither is given, and panics
+/// if either is invalid.
+pub fn parse_options(code: Option<&str>, rate: Option<&str>) -> Option<(Code, Puncture)> {
+    if code.is_none() && rate.is_none() {
+        return None;
+    }
+
+    let code = code.map_or(Code::k7(), |c| {
+        Code::parse(c).expect("invalid convolutional code")
+    });
+
+    let puncture = match rate {
+        Some(r) => {
+            assert!(code.outputs() == 2, "code rates need a rate 1/2 code");
+            Puncture::rate(r).expect("invalid code rate")
+        },
+        None => Puncture::none(code.outputs()),
+    };
+
+    Some((code, puncture))
+}
+
+pub struct Encoder {
+    code: Code,
+    puncture: Puncture,
+    state: usize,
+    /// Position in the puncturing pattern.
+    pos: usize,
+}
+
+impl Encoder {
+    pub fn new(code: Code) -> Encoder {
+        let outputs = code.outputs();
+        Encoder::punctured(code, Puncture::none(outputs))
+    }
+
+    /// Create an encoder sending only the coded bits kept by the given pattern, which
+    /// must have a row per generator.
+    pub fn punctured(code: Code, puncture: Puncture) -> Encoder {
+        assert!(puncture.outputs == code.outputs());
+
+        Encoder {
+            code: code,
+            puncture: puncture,
+            state: 0,
+            pos: 0,
+        }
+    }
+
+    /// Encode the next input bit, appending the coded bits sent to `out`.
+    pub fn encode(&mut self, bit: u8, out: &mut Vec<u8>) {
+        let coded = self.code.branch(self.state, bit);
+        self.state = self.code.next_state(self.state, bit);
+
+        for i in 0..self.code.outputs() {
+            if self.puncture.keep(self.pos) {
+                out.push((coded >> i & 1) as u8);
+            }
+
+            self.pos += 1;
+        }
+    }
+
+    /// Encode the tail bits that return the encoder to the zero state.
+    pub fn finish(&mut self, out: &mut Vec<u8>) {
+        for _ in 1..self.code.constraint() {
+            self.encode(0, out);
+        }
+    }
+}
+
+pub struct Viterbi {
+    code: Code,
+    puncture: Puncture,
+    /// Position in the puncturing pattern.
+    pos: usize,
+    /// Received values for the current input bit, with erasures filled in.
+    received: Vec<f32>,
+    /// Coded bits on the branch for each state and input bit, indexed by state * 2 + bit.
+    branches: Vec<u32>,
+    /// Metric of the best path into each state, larger being more likely.
+    metrics: Vec<f32>,
+    /// Oldest bit of the previous state on the best path into each state, for each
+    /// undecided step.
+    decisions: VecDeque<Vec<u8>>,
+    depth: usize,
+}
+
+impl Viterbi {
+    pub fn new(code: Code) -> Viterbi {
+        let outputs = code.outputs();
+        Viterbi::punctured(code, Puncture::none(outputs))
+    }
+
+    /// Create a decoder for bits sent with the given puncturing pattern.
+    pub fn punctured(code: Code, puncture: Puncture) -> Viterbi {
+        assert!(puncture.outputs == code.outputs());
+
+        let states = code.states();
+
+        let branches = (0..states * 2).map(|x| {
+            code.branch(x / 2, (x % 2) as u8)
+        }).collect();
+
+        // The encoder starts in the zero state.
+        let mut metrics = vec![std::f32::NEG_INFINITY; states];
+        metrics[0] = 0.0;
+
+        Viterbi {
+            depth: code.constraint() * DEPTH_PER_CONSTRAINT,
+            received: Vec::with_capacity(code.outputs()),
+            code: code,
+            puncture: puncture,
+            pos: 0,
+            branches: branches,
+            metrics: metrics,
+            decisions: VecDeque::new(),
+        }
+    }
+
+    /// Decode the next received hard bit, appending any decided input bits to `out`.
+    pub fn add_bit(&mut self, bit: u8, out: &mut Vec<u8>) {
+        self.add_llr(if bit == 1 { 1.0 } else { -1.0 }, out);
+    }
+
+    /// Decode the next received soft bit, given as ln(P(b = 1) / P(b = 0)), appending
+    /// any decided input bits to `out`.
+    pub fn add_llr(&mut self, llr: f32, out: &mut Vec<u8>) {
+        self.erasures(out);
+        self.push(llr, out);
+        self.erasures(out);
+    }
+
+    /// Decode the rest of the bits, ending in the zero state, and append them to `out`
+    /// without the tail bits. A partly received final input bit is dropped.
+    pub fn finish(&mut self, out: &mut Vec<u8>) {
+        let tail = self.code.constraint() - 1;
+        let keep = self.decisions.len().saturating_sub(tail);
+
+        let mut bits = self.traceback(0);
+        bits.truncate(keep);
+        out.extend_from_slice(&bits[..]);
+
+        self.decisions.clear();
+    }
+
+    /// Fill in erasures for the punctured bits up to the next bit sent.
+    fn erasures(&mut self, out: &mut Vec<u8>) {
+        while !self.puncture.keep(self.pos) {
+            self.push(0.0, out);
+        }
+    }
+
+    fn push(&mut self, llr: f32, out: &mut Vec<u8>) {
+        self.received.push(llr);
+        self.pos += 1;
+
+        if self.received.len() == self.code.outputs() {
+            self.step();
+            self.received.clear();
+
+            if self.decisions.len() > self.depth {
+                let best = (0..self.metrics.len()).fold(0, |best, s| {
+                    if self.metrics[s] > self.metrics[best] { s } else { best }
+                });
+
+                out.push(self.traceback(best)[0]);
+                self.decisions.pop_front();
+            }
+        }
+    }
+
+    /// Extend the best path into each state by one input bit.
+    fn step(&mut self) {
+        let states = self.code.states();
+        let top = self.code.constraint() - 2;
+
+        let mut metrics = vec![0.0; states];
+        let mut decisions = vec![0; states];
+
+        for (next, m) in metrics.iter_mut().enumerate() {
+            // The input bit is shifted into the top of the state, and the oldest bit
+            // of the previous state is shifted out.
+            let bit = next >> top;
+            let prev = (next << 1) & (states - 1);
+
+            let (a, b) = (self.path(prev, bit), self.path(prev | 1, bit));
+
+            if b > a {
+                *m = b;
+                decisions[next] = 1;
+            } else {
+                *m = a;
+            }
+        }
+
+        // Keep the metrics from growing without bound.
+        let max = metrics.iter().fold(std::f32::NEG_INFINITY, |m, &x| m.max(x));
+
+        for m in metrics.iter_mut() {
+            *m -= max;
+        }
+
+        self.metrics = metrics;
+        self.decisions.push_back(decisions);
+    }
+
+    /// Compute the metric of the path through the given state and input bit, correlating
+    /// the received values with the branch's coded bits.
+    fn path(&self, state: usize, bit: usize) -> f32 {
+        let coded = self.branches[state * 2 + bit];
+
+        self.received.iter().enumerate().fold(self.metrics[state], |m, (i, &llr)| {
+            if coded >> i & 1 == 1 { m + llr } else { m - llr }
+        })
+    }
+
+    /// Trace the best path back from the given state, returning its input bits in
+    /// order from the oldest undecided step.
+    fn traceback(&self, state: usize) -> Vec<u8> {
+        let top = self.code.constraint() - 2;
+        let mask = self.code.states() - 1;
+
+        let mut state = state;
+        let mut bits = vec![0; self.decisions.len()];
+
+        for (bit, decisions) in bits.iter_mut().zip(self.decisions.iter()).rev() {
+            *bit = (state >> top) as u8;
+            state = (state << 1 & mask) | decisions[state] as usize;
+        }
+
+        bits
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use prbs::{Generator, Pattern};
+    use super::{Code, Puncture, Encoder, Viterbi, parse_options};
+
+    fn data(len: usize) -> Vec<u8> {
+        let mut g = Generator::new(Pattern::PRBS9);
+        (0..len).map(|_| g.next_bit()).collect()
+    }
+
+    fn encode(code: &Code, puncture: &Puncture, data: &[u8]) -> Vec<u8> {
+        let mut e = Encoder::punctured(code.clone(), puncture.clone());
+        let mut coded = vec![];
+
+        for &b in data {
+            e.encode(b, &mut coded);
+        }
+
+        e.finish(&mut coded);
+        coded
+    }
+
+    #[test]
+    fn test_encoder() {
+        // The impulse response interleaves the generator taps.
+        let mut e = Encoder::new(Code::k7());
+        let mut coded = vec![];
+
+        e.encode(1, &mut coded);
+        e.finish(&mut coded);
+
+        assert_eq!(coded, [1, 1, 1, 0, 1, 1, 1, 1, 0, 0, 0, 1, 1, 1]);
+
+        // Puncturing to 3/4 keeps 4 of every 6 coded bits.
+        let p = Puncture::rate("3/4").unwrap();
+        assert_eq!(p.ratio(), (3, 4));
+        assert_eq!(encode(&Code::k7(), &p, &[1, 0, 0]),
+                   [1, 1, 0, 1, 1, 1, 0, 0, 1, 1, 0, 0]);
+
+        assert_eq!(Code::parse("3:7,5").unwrap().generators, [7, 5]);
+        assert!(Code::parse("3:7,9").is_none());
+        assert!(Code::parse("3:17").is_none());
+    }
+
+    #[test]
+    fn test_parse_options() {
+        assert!(parse_options(None, None).is_none());
+
+        // A code rate punctures the default code.
+        let (code, p) = parse_options(None, Some("3/4")).unwrap();
+        assert_eq!(code.generators, Code::k7().generators);
+        assert_eq!(p.ratio(), (3, 4));
+
+        // A code alone is sent unpunctured.
+        let (code, p) = parse_options(Some("5:23,35,37"), None).unwrap();
+        assert_eq!(code.outputs(), 3);
+        assert_eq!(p.ratio(), (1, 3));
+    }
+
+    #[test]
+    fn test_hard() {
+        let data = data(500);
+
+        for rate in &["1/2", "2/3", "3/4", "5/6", "7/8"] {
+            let p = Puncture::rate(rate).unwrap();
+            let mut coded = encode(&Code::k7(), &p, &data);
+
+            // Spread out errors are all corrected, with higher rates needing them
+            // further apart.
+            let spacing = 23 * p.ratio().1;
+
+            for i in 0..coded.len() / spacing {
+                coded[i * spacing + i % 5] ^= 1;
+            }
+
+            let mut v = Viterbi::punctured(Code::k7(), p);
+            let mut decoded = vec![];
+
+            for &b in &coded {
+                v.add_bit(b, &mut decoded);
+            }
+
+            v.finish(&mut decoded);
+            assert_eq!(decoded, data);
+        }
+    }
+
+    #[test]
+    fn test_soft() {
+        let data = data(300);
+        let code = Code::new(5, vec![0o23, 0o35, 0o37]);
+        let coded = encode(&code, &Puncture::none(3), &data);
+
+        // Weak wrong bits are outweighed by confident right ones, which would be too
+        // many errors for hard decisions.
+        let llrs: Vec<f32> = coded.iter().enumerate().map(|(i, &b)| {
+            let llr = if b == 1 { 2.0 } else { -2.0 };
+            if i % 3 == 1 { -llr * 0.1 } else { llr }
+        }).collect();
+
+        let mut v = Viterbi::new(code);
+        let mut decoded = vec![];
+
+        for &l in &llrs {
+            v.add_llr(l, &mut decoded);
+        }
+
+        v.finish(&mut decoded);
+        assert_eq!(decoded, data);
+    }
+}
@@ -1,5 +1,9 @@
 use std;
 
+use conv;
+use prbs;
+use rates::Rates;
+
 #[derive(PartialEq, Eq)]
 pub enum SourceUpdate<'a> {
     Changed(&'a [u8]),
@@ -11,24 +15,42 @@ pub trait Source {
     fn next(&mut self) -> SourceUpdate;
 }
 
+/// Lets a source chosen at runtime be wrapped, such as by `EvenOddOffset`.
+impl<'a> Source for Box<Source + 'a> {
+    fn next(&mut self) -> SourceUpdate {
+        (**self).next()
+    }
+}
+
+/// Ticks at the start of each symbol. When the sample rate isn't a multiple of the baud
+/// rate, the symbols alternate between the nearest whole numbers of samples so the
+/// average is exact, like a Bresenham line.
 struct SymbolClock {
-    samples_per_symbol: usize,
+    baud_rate: usize,
+    sample_rate: usize,
+    /// Symbol phase, in units of 1/(sample rate) symbols.
     counter: usize,
 }
 
 impl SymbolClock {
-    pub fn new(samples_per_symbol: usize) -> SymbolClock {
+    pub fn new(rates: Rates) -> SymbolClock {
         SymbolClock {
-            samples_per_symbol: samples_per_symbol,
-            counter: samples_per_symbol - 1,
+            baud_rate: rates.baud_rate,
+            sample_rate: rates.sample_rate,
+            // Tick on the first sample.
+            counter: rates.sample_rate - rates.baud_rate,
         }
     }
 
     pub fn next(&mut self) -> bool {
-        self.counter += 1;
-        self.counter %= self.samples_per_symbol;
+        self.counter += self.baud_rate;
 
-        self.counter == 0
+        if self.counter >= self.sample_rate {
+            self.counter -= self.sample_rate;
+            true
+        } else {
+            false
+        }
     }
 }
 
@@ -40,12 +62,10 @@ pub struct Bits<'a> {
 }
 
 impl<'a> Bits<'a> {
-    pub fn new(bits: &'a [u8], samples_per_symbol: usize, bits_per_symbol: usize)
-        -> Bits<'a>
-    {
+    pub fn new(bits: &'a [u8], rates: Rates, bits_per_symbol: usize) -> Bits<'a> {
         Bits {
             bits: bits,
-            clock: SymbolClock::new(samples_per_symbol),
+            clock: SymbolClock::new(rates),
             bits_per_symbol: bits_per_symbol,
             idx: 0,
         }
@@ -85,15 +105,14 @@ pub struct EvenOddOffset<D: Source> {
 }
 
 impl<D: Source> EvenOddOffset<D> {
-    pub fn new(data: D, samples_per_symbol: usize, bits_per_symbol: usize)
-        -> EvenOddOffset<D>
-    {
+    pub fn new(data: D, rates: Rates, bits_per_symbol: usize) -> EvenOddOffset<D> {
         assert!(bits_per_symbol == 2);
-        assert!(samples_per_symbol % bits_per_symbol == 0);
 
         EvenOddOffset {
             data: data,
-            clock: SymbolClock::new(samples_per_symbol / bits_per_symbol),
+            // Tick every half symbol.
+            clock: SymbolClock::new(Rates::new(rates.baud_rate * bits_per_symbol,
+                                               rates.sample_rate)),
             cur: [0, 0],
         }
     }
@@ -129,12 +148,10 @@ pub struct AsciiBits<R: std::io::Read> {
 }
 
 impl<R: std::io::Read> AsciiBits<R> {
-    pub fn new(stream: R, samples_per_symbol: usize, bits_per_symbol: usize)
-        -> AsciiBits<R>
-    {
+    pub fn new(stream: R, rates: Rates, bits_per_symbol: usize) -> AsciiBits<R> {
         AsciiBits {
             stream: stream,
-            clock: SymbolClock::new(samples_per_symbol),
+            clock: SymbolClock::new(rates),
             bits: vec![0; bits_per_symbol],
         }
     }
@@ -185,15 +202,239 @@ impl<R: std::io::Read> Source for AsciiBits<R> {
     }
 }
 
+/// Order in which the bits of each byte are sent.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BitOrder {
+    MsbFirst,
+    LsbFirst,
+}
+
+/// How to complete the final symbol when the stream ends partway through it.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Padding {
+    /// Drop the partial symbol.
+    Drop,
+    /// Fill out the symbol with zero bits.
+    Zeros,
+    /// Fill out the symbol with one bits.
+    Ones,
+}
+
+/// Unpacks the raw bytes of a stream into symbols, so arbitrary binary data can be sent.
+pub struct Bytes<R: std::io::Read> {
+    stream: R,
+    clock: SymbolClock,
+    order: BitOrder,
+    padding: Padding,
+    /// Current byte, shifted so the next bit is in the MSB or LSB.
+    byte: u8,
+    /// Number of bits left in the current byte.
+    left: usize,
+    bits: Vec<u8>,
+}
+
+impl<R: std::io::Read> Bytes<R> {
+    pub fn new(stream: R, rates: Rates, bits_per_symbol: usize, order: BitOrder,
+               padding: Padding)
+        -> Bytes<R>
+    {
+        Bytes {
+            stream: stream,
+            clock: SymbolClock::new(rates),
+            order: order,
+            padding: padding,
+            byte: 0,
+            left: 0,
+            bits: vec![0; bits_per_symbol],
+        }
+    }
+
+    fn next_bit(&mut self) -> Option<u8> {
+        if self.left == 0 {
+            let mut buf = [0; 1];
+
+            self.byte = match self.stream.read(&mut buf) {
+                Ok(1) => buf[0],
+                _ => return None,
+            };
+
+            self.left = 8;
+        }
+
+        self.left -= 1;
+
+        Some(match self.order {
+            BitOrder::MsbFirst => {
+                let bit = self.byte >> 7;
+                self.byte <<= 1;
+                bit
+            },
+            BitOrder::LsbFirst => {
+                let bit = self.byte & 1;
+                self.byte >>= 1;
+                bit
+            },
+        })
+    }
+
+    fn read_bits(&mut self) -> bool {
+        for i in 0..self.bits.len() {
+            self.bits[i] = match self.next_bit() {
+                Some(b) => b,
+                // Only a symbol that got some bits is padded.
+                None if i == 0 => return false,
+                None => match self.padding {
+                    Padding::Drop => return false,
+                    Padding::Zeros => 0,
+                    Padding::Ones => 1,
+                },
+            }
+        }
+
+        true
+    }
+}
+
+impl<R: std::io::Read> Source for Bytes<R> {
+    fn next(&mut self) -> SourceUpdate {
+        if self.clock.next() {
+            if self.read_bits() {
+                SourceUpdate::Changed(&self.bits[..])
+            } else {
+                SourceUpdate::Finished
+            }
+        } else {
+            SourceUpdate::Unchanged(&self.bits[..])
+        }
+    }
+}
+
+/// Generates symbols from a PRBS test pattern.
+pub struct Prbs {
+    gen: prbs::Generator,
+    clock: SymbolClock,
+    bits: Vec<u8>,
+    /// Number of symbols left to send.
+    remaining: usize,
+}
+
+impl Prbs {
+    /// Create a new source sending at least the given number of bits of the pattern,
+    /// rounded up to a whole symbol.
+    pub fn new(pattern: prbs::Pattern, rates: Rates, bits_per_symbol: usize, bits: usize)
+        -> Prbs
+    {
+        Prbs {
+            gen: prbs::Generator::new(pattern),
+            clock: SymbolClock::new(rates),
+            bits: vec![0; bits_per_symbol],
+            remaining: (bits + bits_per_symbol - 1) / bits_per_symbol,
+        }
+    }
+}
+
+impl Source for Prbs {
+    fn next(&mut self) -> SourceUpdate {
+        if !self.clock.next() {
+            return SourceUpdate::Unchanged(&self.bits[..]);
+        }
+
+        if self.remaining == 0 {
+            return SourceUpdate::Finished;
+        }
+
+        self.remaining -= 1;
+
+        for b in self.bits.iter_mut() {
+            *b = self.gen.next_bit();
+        }
+
+        SourceUpdate::Changed(&self.bits[..])
+    }
+}
+
+/// Encodes the bits from the wrapped source with a convolutional code and regroups the
+/// coded bits into symbols. The wrapped source only supplies bits, so its own symbol
+/// timing is ignored. The code is terminated when the source finishes, and the final
+/// symbol is padded with zeros, which may decode as a few extra zero bits.
+pub struct Encoded<D: Source> {
+    data: D,
+    encoder: conv::Encoder,
+    clock: SymbolClock,
+    /// Coded bits not yet sent.
+    pending: Vec<u8>,
+    finished: bool,
+    bits: Vec<u8>,
+}
+
+impl<D: Source> Encoded<D> {
+    pub fn new(data: D, encoder: conv::Encoder, rates: Rates, bits_per_symbol: usize)
+        -> Encoded<D>
+    {
+        Encoded {
+            data: data,
+            encoder: encoder,
+            clock: SymbolClock::new(rates),
+            pending: vec![],
+            finished: false,
+            bits: vec![0; bits_per_symbol],
+        }
+    }
+
+    fn fill(&mut self) {
+        while self.pending.len() < self.bits.len() && !self.finished {
+            match self.data.next() {
+                SourceUpdate::Finished => {
+                    self.encoder.finish(&mut self.pending);
+                    self.finished = true;
+                },
+                SourceUpdate::Changed(b) => for &bit in b {
+                    self.encoder.encode(bit, &mut self.pending);
+                },
+                SourceUpdate::Unchanged(_) => {},
+            }
+        }
+    }
+}
+
+impl<D: Source> Source for Encoded<D> {
+    fn next(&mut self) -> SourceUpdate {
+        if !self.clock.next() {
+            return SourceUpdate::Unchanged(&self.bits[..]);
+        }
+
+        self.fill();
+
+        if self.pending.is_empty() {
+            return SourceUpdate::Finished;
+        }
+
+        let n = std::cmp::min(self.bits.len(), self.pending.len());
+
+        for (i, b) in self.bits.iter_mut().enumerate() {
+            *b = if i < n { self.pending[i] } else { 0 };
+        }
+
+        self.pending.drain(..n);
+
+        SourceUpdate::Changed(&self.bits[..])
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std;
     use std::io::Write;
-    use super::{Bits, Source, SourceUpdate, SymbolClock, EvenOddOffset, AsciiBits};
+
+    use conv;
+    use prbs;
+    use rates::Rates;
+    use super::{Bits, Source, SourceUpdate, SymbolClock, EvenOddOffset, AsciiBits, Bytes,
+                BitOrder, Padding, Prbs, Encoded};
 
     #[test]
     fn test_symbol_clock() {
-        let mut bc = SymbolClock::new(5);
+        let mut bc = SymbolClock::new(Rates::new(1, 5));
 
         assert!(bc.next());
         assert!(!bc.next());
@@ -206,13 +447,27 @@ mod test {
         assert!(!bc.next());
         assert!(!bc.next());
         assert!(bc.next());
+
+        // 1200 baud at 44.1kHz alternates between 36 and 37 samples per symbol, keeping
+        // the exact rate over any whole second.
+        let mut bc = SymbolClock::new(Rates::new(1200, 44100));
+        let ticks: Vec<usize> = (0..44100).filter(|_| bc.next()).collect();
+
+        assert_eq!(ticks.len(), 1200);
+        assert_eq!(ticks[0], 0);
+
+        for w in ticks.windows(2) {
+            assert!(w[1] - w[0] == 36 || w[1] - w[0] == 37);
+        }
+
+        assert!(bc.next());
     }
 
     #[test]
     fn test_bits() {
         const BITS: &'static [u8] = &[1, 0, 1, 1];
 
-        let mut ds = Bits::new(BITS, 3, 2);
+        let mut ds = Bits::new(BITS, Rates::new(1, 3), 2);
 
         assert!(ds.next() == SourceUpdate::Changed(&[1, 0]));
         assert!(ds.next() == SourceUpdate::Unchanged(&[1, 0]));
@@ -227,8 +482,8 @@ mod test {
     fn test_evenodd() {
         const BITS: &'static [u8] = &[1, 1, 1, 0, 0, 1];
 
-        let ds = Bits::new(BITS, 4, 2);
-        let mut eo = EvenOddOffset::new(ds, 4, 2);
+        let ds = Bits::new(BITS, Rates::new(1, 4), 2);
+        let mut eo = EvenOddOffset::new(ds, Rates::new(1, 4), 2);
 
         assert!(eo.next() == SourceUpdate::Changed(&[1, 0]));
         assert!(eo.next() == SourceUpdate::Unchanged(&[1, 0]));
@@ -254,7 +509,7 @@ mod test {
 
         {
             let f = std::fs::File::open("ascii.bits").unwrap();
-            let mut a = AsciiBits::new(f, 1, 3);
+            let mut a = AsciiBits::new(f, Rates::new(1, 1), 3);
 
             assert!(a.read_bits());
             assert!(a.read_bits());
@@ -264,7 +519,7 @@ mod test {
 
         {
             let f = std::fs::File::open("ascii.bits").unwrap();
-            let mut a = AsciiBits::new(f, 2, 3);
+            let mut a = AsciiBits::new(f, Rates::new(1, 2), 3);
 
             assert!(a.next() == SourceUpdate::Changed(&[0,0,0]));
             assert!(a.next() == SourceUpdate::Unchanged(&[0,0,0]));
@@ -277,4 +532,84 @@ mod test {
 
         std::fs::remove_file("ascii.bits").unwrap();
     }
+
+    #[test]
+    fn test_bytes() {
+        const BYTES: &'static [u8] = &[0b10110001, 0b01000000];
+
+        let mut b = Bytes::new(BYTES, Rates::new(1, 2), 3, BitOrder::MsbFirst,
+                               Padding::Zeros);
+
+        assert!(b.next() == SourceUpdate::Changed(&[1,0,1]));
+        assert!(b.next() == SourceUpdate::Unchanged(&[1,0,1]));
+        assert!(b.next() == SourceUpdate::Changed(&[1,0,0]));
+        b.next();
+        assert!(b.next() == SourceUpdate::Changed(&[0,1,0]));
+        b.next();
+        assert!(b.next() == SourceUpdate::Changed(&[1,0,0]));
+        b.next();
+        assert!(b.next() == SourceUpdate::Changed(&[0,0,0]));
+        b.next();
+        assert!(b.next() == SourceUpdate::Changed(&[0,0,0]));
+        b.next();
+        assert!(b.next() == SourceUpdate::Finished);
+
+        let mut b = Bytes::new(BYTES, Rates::new(1, 1), 3, BitOrder::LsbFirst,
+                               Padding::Ones);
+
+        assert!(b.next() == SourceUpdate::Changed(&[1,0,0]));
+        assert!(b.next() == SourceUpdate::Changed(&[0,1,1]));
+        assert!(b.next() == SourceUpdate::Changed(&[0,1,0]));
+        assert!(b.next() == SourceUpdate::Changed(&[0,0,0]));
+        assert!(b.next() == SourceUpdate::Changed(&[0,0,1]));
+        assert!(b.next() == SourceUpdate::Changed(&[0,1,1]));
+        assert!(b.next() == SourceUpdate::Finished);
+
+        // The 16 bits leave one over, which is dropped.
+        let mut b = Bytes::new(BYTES, Rates::new(1, 1), 3, BitOrder::MsbFirst,
+                               Padding::Drop);
+
+        for _ in 0..5 {
+            assert!(b.read_bits());
+        }
+
+        assert!(!b.read_bits());
+    }
+
+    #[test]
+    fn test_prbs() {
+        let mut g = prbs::Generator::new(prbs::Pattern::PRBS7);
+        let expected: Vec<u8> = (0..9).map(|_| g.next_bit()).collect();
+
+        // Eight bits round up to three symbols.
+        let mut p = Prbs::new(prbs::Pattern::PRBS7, Rates::new(1, 2), 3, 8);
+
+        assert!(p.next() == SourceUpdate::Changed(&expected[0..3]));
+        assert!(p.next() == SourceUpdate::Unchanged(&expected[0..3]));
+        assert!(p.next() == SourceUpdate::Changed(&expected[3..6]));
+        p.next();
+        assert!(p.next() == SourceUpdate::Changed(&expected[6..9]));
+        p.next();
+        assert!(p.next() == SourceUpdate::Finished);
+    }
+
+    #[test]
+    fn test_encoded() {
+        const BITS: &'static [u8] = &[1, 0, 0];
+
+        let encoder = conv::Encoder::punctured(conv::Code::k7(),
+                                               conv::Puncture::rate("3/4").unwrap());
+
+        // Three bits and the tail code to 12 bits, with the last symbol padded.
+        let mut e = Encoded::new(Bits::new(BITS, Rates::new(1, 3), 1), encoder,
+                                 Rates::new(1, 2), 5);
+
+        assert!(e.next() == SourceUpdate::Changed(&[1, 1, 0, 1, 1]));
+        assert!(e.next() == SourceUpdate::Unchanged(&[1, 1, 0, 1, 1]));
+        assert!(e.next() == SourceUpdate::Changed(&[1, 0, 0, 1, 1]));
+        e.next();
+        assert!(e.next() == SourceUpdate::Changed(&[0, 0, 0, 0, 0]));
+        e.next();
+        assert!(e.next() == SourceUpdate::Finished);
+    }
 }
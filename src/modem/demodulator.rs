@@ -2,8 +2,10 @@ extern crate num;
 
 use super::{carrier, pll, fir};
 
-const LOCK_SAMPLES: usize = 64;
-
+/// Mixes a received carrier down to baseband I/Q. The carrier loop locks onto the
+/// preamble, then coasts at its phase and frequency estimate for the rest of the
+/// stream. Continuous tracking of the phase and frequency left over after the lock is
+/// delegated to a `Tracker` at the symbol rate, after the lowpass and timing recovery.
 pub struct Demodulator<'a, S>
     where S: Iterator<Item = num::Complex<f32>>
 {
@@ -17,22 +19,38 @@ pub struct Demodulator<'a, S>
 impl<'a, S> Demodulator<'a, S>
     where S: Iterator<Item = num::Complex<f32>>
 {
-    pub fn new<F>(carrier: carrier::Carrier, sig: S, lp: F) -> Demodulator<'a, S>
+    pub fn new<F>(carrier: carrier::Carrier, sig: S, lp: F, pll: pll::PLL)
+        -> Demodulator<'a, S>
         where F: Fn() -> fir::FIRFilter<'a>
     {
         Demodulator {
             carrier: carrier,
             sig: sig,
-            pll: pll::PLL::new(),
+            pll: pll,
             lpi: lp(),
             lpq: lp(),
         }
     }
 
-    pub fn lock_phase(&mut self) {
-        for _ in 0..LOCK_SAMPLES {
-            self.pll.handle(self.carrier.next(), self.sig.next().unwrap());
+    /// Lock onto the phase of an unmodulated carrier over the given number of samples.
+    /// Afterwards the loop coasts at the locked frequency, since tracking the lowpassed
+    /// baseband would put the filter delay inside the loop. Any residual phase is left
+    /// to be tracked at the symbol rate. Returns false if the signal ends first.
+    pub fn lock_phase(&mut self, samples: usize) -> bool {
+        for _ in 0..samples {
+            match self.sig.next() {
+                Some(x) => self.pll.handle(self.carrier.next(), x),
+                None => return false,
+            }
         }
+
+        true
+    }
+
+    /// Get the carrier tracking loop, which holds the current phase and frequency
+    /// offset estimates.
+    pub fn pll(&self) -> &pll::PLL {
+        &self.pll
     }
 }
 
@@ -47,11 +65,206 @@ impl<'a, S> Iterator for Demodulator<'a, S>
             None => return None,
         };
 
-        let phase = self.carrier.next() + self.pll.phase_offset;
+        let phase = self.carrier.next() + self.pll.phase();
+
+        let i = 2.0 * self.lpi.add(x * phase.cos());
+        let q = 2.0 * self.lpq.add(x * -phase.sin());
+
+        self.pll.coast();
+
+        Some((i, q))
+    }
+}
+
+/// Tracks the residual carrier phase and frequency of a stream of symbols, one sample
+/// per symbol such as from timing recovery, and derotates each symbol by the estimate.
+/// The phase detector only sees the symbol instants, where a suppressed-carrier
+/// constellation actually sits on its points.
+pub struct Tracker<S>
+    where S: Iterator<Item = (f32, f32)>
+{
+    sig: S,
+    pll: pll::PLL,
+    detector: Box<pll::PhaseDetector>,
+}
+
+impl<S> Tracker<S>
+    where S: Iterator<Item = (f32, f32)>
+{
+    /// Create a new tracker over the given symbols with the given phase detector and
+    /// loop, whose bandwidth is normalized to the symbol rate.
+    pub fn new(sig: S, detector: Box<pll::PhaseDetector>, pll: pll::PLL) -> Tracker<S> {
+        Tracker {
+            sig: sig,
+            pll: pll,
+            detector: detector,
+        }
+    }
+
+    /// Get the tracking loop, which holds the current phase (radians) and frequency
+    /// (radians/symbol) offset estimates.
+    pub fn pll(&self) -> &pll::PLL {
+        &self.pll
+    }
+}
+
+impl<S> Iterator for Tracker<S>
+    where S: Iterator<Item = (f32, f32)>
+{
+    type Item = (f32, f32);
+
+    fn next(&mut self) -> Option<(f32, f32)> {
+        let (i, q) = match self.sig.next() {
+            Some(x) => x,
+            None => return None,
+        };
+
+        let (sin, cos) = self.pll.phase().sin_cos();
+
+        let i_rot = i * cos + q * sin;
+        let q_rot = q * cos - i * sin;
+
+        let err = self.detector.error(i_rot, q_rot);
+        self.pll.update(err);
+
+        Some((i_rot, q_rot))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+    use std::f64;
+
+    use num::Complex;
+
+    use carrier::Carrier;
+    use digital::DigitalPhasor;
+    use digital::qam::QAM;
+    use digital::qpsk::QPSK;
+    use fir::FIRFilter;
+    use fir::design::{lowpass, Window};
+    use freq::Freq;
+    use pll::{PLL, Costas, DecisionDirected};
+    use super::{Demodulator, Tracker};
+
+    // Generate random symbols from the given phasor, rotated by a phase that starts at
+    // the given offset and drifts by the given amount per symbol.
+    fn symbols<P: DigitalPhasor>(p: &P, offset: f32, drift: f32, count: usize)
+        -> Vec<(f32, f32)>
+    {
+        let mut state = 0x9abcu32;
+        let mut bits = vec![0; p.bits_per_symbol()];
+
+        (0..count).map(|n| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+
+            for (k, b) in bits.iter_mut().enumerate() {
+                *b = (state >> (16 + k)) as u8 & 1;
+            }
+
+            let (i, q) = p.next(0, &bits[..]);
+            let (sin, cos) = (offset + drift * n as f32).sin_cos();
+
+            (i * cos - q * sin, i * sin + q * cos)
+        }).collect()
+    }
+
+    #[test]
+    fn test_tracker() {
+        let qam = QAM::new(6, 0.0, 1.0);
+        let sig = symbols(&qam, 0.1, 0.001, 3000);
+
+        let mut t = Tracker::new(sig.clone().into_iter(),
+                                 Box::new(DecisionDirected::new(QAM::new(6, 0.0, 1.0))),
+                                 PLL::new(0.02, 0.707));
+
+        let out: Vec<(f32, f32)> = t.by_ref().collect();
+        assert!((t.pll().freq() - 0.001).abs() < 1e-4);
+
+        // Once locked, the derotated symbols land back on the constellation.
+        let ideal = symbols(&qam, 0.0, 0.0, 3000);
+
+        for (&(i, q), &(ei, eq)) in out.iter().zip(ideal.iter()).skip(1000) {
+            assert!((i - ei).abs() < 0.01 && (q - eq).abs() < 0.01);
+        }
+
+        // A Costas loop locks to within a multiple of a quarter turn.
+        let sig = symbols(&QPSK::new(0.0, 1.0), 0.3, 0.0, 2000);
+        let mut t = Tracker::new(sig.into_iter(),
+                                 Box::new(Costas::new(2, PI / 4.0)),
+                                 PLL::new(0.02, 0.707));
+
+        for _ in t.by_ref() {}
+
+        let residual = (0.3 - t.pll().phase()) / (PI / 2.0);
+        assert!((residual - residual.round()).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_demodulator() {
+        const SPS: usize = 40;
+        const PREAMBLE: usize = 4000;
+
+        // The carrier starts 2 Hz high, then drifts another 0.5 Hz once the preamble
+        // ends.
+        let offset = Freq::from_hz(2.0, 8000).sample_freq();
+        let drift = Freq::from_hz(0.5, 8000).sample_freq();
+        let carrier = Freq::new(1000, 8000);
+
+        let ideal = symbols(&QPSK::new(0.0, 1.0), 0.0, 0.0, 1500);
+
+        // Accumulate the phase at full precision, so rounding doesn't skew the offset.
+        let mut phase = 0.3f64;
+
+        let sig: Vec<Complex<f32>> = (0..PREAMBLE + ideal.len() * SPS).map(|n| {
+            let (i, q) = if n < PREAMBLE { (1.0, 0.0) } else {
+                ideal[(n - PREAMBLE) / SPS]
+            };
+
+            let x = Complex::new(i, q) * Complex::from_polar(&1.0, &(phase as f32));
+            let step = carrier.sample_freq() + offset +
+                       if n < PREAMBLE { 0.0 } else { drift };
+            phase = (phase + step as f64) % (2.0 * f64::consts::PI);
+
+            x
+        }).collect();
+
+        let taps = lowpass(carrier, 65, Window::Hamming);
+        let mut demod = Demodulator::new(Carrier::new(carrier), sig.into_iter(),
+                                         || FIRFilter::new(&taps[..]),
+                                         PLL::new(0.002, 0.707));
+
+        assert!(demod.lock_phase(PREAMBLE));
+        assert!((demod.pll().freq() - offset).abs() < 1e-5);
+
+        // Frequency offset (radians/symbol) left for the tracker, once the loop coasts.
+        let residual = (offset + drift - demod.pll().freq()) * SPS as f32;
+
+        // Take the middle of each symbol, past the lowpass delay.
+        let sampled = demod.by_ref().skip(taps.len() / 2 + SPS / 2).enumerate()
+                           .filter(|&(n, _)| n % SPS == 0).map(|(_, x)| x);
+
+        let mut t = Tracker::new(sampled, Box::new(Costas::new(2, PI / 4.0)),
+                                 PLL::new(0.02, 0.707));
+
+        let out: Vec<(f32, f32)> = t.by_ref().collect();
+
+        // The tracker follows the offset left after the lock.
+        assert!((t.pll().freq() - residual).abs() < 1e-4);
+
+        // And the constellation stays put, within a multiple of a quarter turn.
+        for (&(i, q), &(ei, eq)) in out.iter().zip(ideal.iter()).skip(500) {
+            let err = (Complex::new(i, q) / Complex::new(ei, eq)).powf(4.0).arg() / 4.0;
+            assert!(err.abs() < 0.05);
+        }
+
+        // A signal that ends during the preamble can't be locked.
+        let sig = vec![Complex::new(1.0, 0.0); 100];
+        let mut demod = Demodulator::new(Carrier::new(carrier), sig.into_iter(),
+                                         || FIRFilter::new(&taps[..]),
+                                         PLL::new(0.002, 0.707));
 
-        Some((
-            2.0 * self.lpi.add(x * phase.cos()),
-            2.0 * self.lpq.add(x * -phase.sin())
-        ))
+        assert!(!demod.lock_phase(PREAMBLE));
     }
 }
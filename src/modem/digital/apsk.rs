@@ -2,20 +2,25 @@
 /// grouped into concentric rings, where each ring is offset from the origin by a radius,
 /// The symbols in each ring are offset from each other by a constant phase angle.
 
+use std;
 use std::f32::consts::PI;
 use std::ops::Range;
 
-use super::DigitalPhasor;
-use super::util::{bytes_to_bits, max_symbol};
+use super::{DigitalPhasor, Slicer};
+use super::llr::{constellation_llr, Metric, SoftSlicer};
+use super::mapping::{Mapping, Natural};
+use super::util::{bits_to_bytes, bytes_to_bits, max_symbol};
 
 /// Provides the APSK phasor.
-pub struct APSK {
+pub struct APSK<M: Mapping = Natural> {
     /// Maximum amplitude.
     amplitude: f32,
     /// Determines total number of symbols over all rings.
     bits_per_symbol: usize,
     /// Ring specifications.
     rings: Vec<Ring>,
+    /// Labelling of the points, indexed in ring order.
+    map: M,
 }
 
 impl APSK {
@@ -23,17 +28,29 @@ impl APSK {
     /// specifications. The rings must be given in the order of the symbols they cover and
     /// in total must cover all possible symbol values for the given symbol size.
     pub fn new(amplitude: f32, bits_per_symbol: usize, rings: Vec<Ring>) -> APSK {
+        APSK::with_mapping(amplitude, bits_per_symbol, rings, Natural)
+    }
+}
+
+impl<M: Mapping> APSK<M> {
+    /// Create a new APSK phasor like `new`, with the given labelling of the points in
+    /// the order the rings cover them.
+    pub fn with_mapping(amplitude: f32, bits_per_symbol: usize, rings: Vec<Ring>, map: M)
+        -> APSK<M>
+    {
         assert!(verify(&rings[..], bits_per_symbol));
+        assert!(map.fits(bits_per_symbol), "mapping doesn't fit the constellation");
 
         APSK {
             amplitude: amplitude,
             bits_per_symbol: bits_per_symbol,
             rings: rings,
+            map: map,
         }
     }
 
     /// Compute the radius and phase for the given symbol.
-    fn common(&self, symbol: u8) -> (f32, f32) {
+    fn common(&self, symbol: u16) -> (f32, f32) {
         let ring = self.rings.iter().find(|r| r.range.contains(symbol)).unwrap();
         let phase = 2.0 * PI * (symbol - ring.range.start) as f32 /
             (ring.range.end - ring.range.start) as f32 + ring.phase;
@@ -42,24 +59,55 @@ impl APSK {
     }
 }
 
-impl DigitalPhasor for APSK {
+impl<M: Mapping> DigitalPhasor for APSK<M> {
     fn bits_per_symbol(&self) -> usize { self.bits_per_symbol }
 
     fn i(&self, _: usize, b: &[u8]) -> f32 {
-        let (r, inner) = self.common(bytes_to_bits(b));
+        let (r, inner) = self.common(self.map.point(bytes_to_bits(b)));
         self.amplitude * r * inner.cos()
     }
 
     fn q(&self, _: usize, b: &[u8]) -> f32 {
-        let (r, inner) = self.common(bytes_to_bits(b));
+        let (r, inner) = self.common(self.map.point(bytes_to_bits(b)));
         self.amplitude * r * inner.sin()
     }
 }
 
+impl<M: Mapping> Slicer for APSK<M> {
+    fn bits_per_symbol(&self) -> usize { self.bits_per_symbol }
+
+    fn slice(&self, i: f32, q: f32, b: &mut [u8]) {
+        let mut best = (0, std::f32::INFINITY);
+
+        // The rings don't form a regular grid, so just search every symbol.
+        for symbol in 0..max_symbol(self.bits_per_symbol) + 1 {
+            let (r, inner) = self.common(symbol as u16);
+            let di = i - self.amplitude * r * inner.cos();
+            let dq = q - self.amplitude * r * inner.sin();
+            let dist = di * di + dq * dq;
+
+            if dist < best.1 {
+                best = (symbol, dist);
+            }
+        }
+
+        bits_to_bytes(self.map.bits(best.0 as u16), b);
+    }
+}
+
+impl<M: Mapping> SoftSlicer for APSK<M> {
+    fn llr(&self, i: f32, q: f32, noise_var: f32, metric: Metric, llrs: &mut [f32]) {
+        constellation_llr(self.bits_per_symbol, |s| {
+            let (r, inner) = self.common(self.map.point(s));
+            (self.amplitude * r * inner.cos(), self.amplitude * r * inner.sin())
+        }, i, q, noise_var, metric, llrs);
+    }
+}
+
 /// Single ring for symbols.
 pub struct Ring {
     /// Symbols contained in this ring.
-    range: Range<u8>,
+    range: Range<u16>,
     /// Radius of ring.
     radius: f32,
     /// Phase offset between symbols.
@@ -70,7 +118,7 @@ impl Ring {
     /// Create a new ring to cover the given range of symbols at the given radius (as a
     /// fraction between 0 and 1 of the maximum amplitude) and with the given phase offset
     /// (radians) between symbols.
-    pub fn new(range: Range<u8>, radius: f32, phase: f32) -> Ring {
+    pub fn new(range: Range<u16>, radius: f32, phase: f32) -> Ring {
         assert!(radius >= 0.0 && radius <= 1.0);
 
         Ring {
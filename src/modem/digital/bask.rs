@@ -1,4 +1,4 @@
-use super::DigitalPhasor;
+use super::{DigitalPhasor, Slicer};
 
 pub struct BASK {
     amplitude: f32,
@@ -23,3 +23,12 @@ impl DigitalPhasor for BASK {
         0.0
     }
 }
+
+impl Slicer for BASK {
+    fn bits_per_symbol(&self) -> usize { 1 }
+
+    fn slice(&self, i: f32, _: f32, b: &mut [u8]) {
+        // Threshold halfway between the off and on amplitudes.
+        b[0] = (i >= self.amplitude / 2.0) as u8;
+    }
+}
@@ -1,56 +1,46 @@
 use freq::Freq;
-use util::mod_trig;
+use nco::NCO;
 
 use super::DigitalPhasor;
 
 pub struct BFSK {
-    deviation: f32,
+    deviation: Freq,
     amplitude: f32,
-    phase: f32,
-    prev: u8,
+    /// Oscillator at the current tone, holding the phase at the start of the current
+    /// bit and retuned for each bit so the phase stays continuous.
+    nco: NCO,
+    /// Sample the current bit started on.
+    start: usize,
 }
 
 impl BFSK {
     pub fn new(d: Freq, a: f32) -> BFSK {
         BFSK {
-            deviation: d.sample_freq(),
+            deviation: d,
             amplitude: a,
-            phase: 0.0,
-            prev: 0,
+            nco: NCO::new(Freq::new(0, d.sample_rate())),
+            start: 0,
         }
     }
-
-    fn inner(&self, s: usize, b: u8) -> f32 {
-        self.rads(s, b) + self.phase
-    }
-
-    fn rads(&self, s: usize, b: u8) -> f32 {
-        b as f32 * self.deviation * s as f32
-    }
 }
 
 impl DigitalPhasor for BFSK {
     fn bits_per_symbol(&self) -> usize { 1 }
 
-    fn i(&self, s: usize, b: &[u8]) -> f32 {
-        self.amplitude * self.inner(s, b[0]).cos()
+    fn i(&self, s: usize, _: &[u8]) -> f32 {
+        self.amplitude * self.nco.sin_cos_ahead(s - self.start).1
     }
 
-    fn q(&self, s: usize, b: &[u8]) -> f32 {
-        self.amplitude * self.inner(s, b[0]).sin()
+    fn q(&self, s: usize, _: &[u8]) -> f32 {
+        self.amplitude * self.nco.sin_cos_ahead(s - self.start).0
     }
 
     fn update(&mut self, s: usize, b: &[u8]) {
-        if b[0] == self.prev {
-            return;
-        }
-
-        self.phase = mod_trig(self.phase + if b[0] == 1 {
-            -self.rads(s, 1)
-        } else {
-            self.rads(s - 1, 1)
-        });
+        // Run the previous tone up to this bit.
+        self.nco.skip(s - self.start);
+        self.start = s;
 
-        self.prev = b[0];
+        self.nco.tune(Freq::from_hz(b[0] as f64 * self.deviation.hz(),
+                                    self.deviation.sample_rate()));
     }
 }
@@ -1,4 +1,4 @@
-use super::DigitalPhasor;
+use super::{DigitalPhasor, Slicer};
 use super::util::bit_to_sign;
 
 pub struct BPSK {
@@ -30,3 +30,12 @@ impl DigitalPhasor for BPSK {
         self.common(b[0]) * self.phase.sin()
     }
 }
+
+impl Slicer for BPSK {
+    fn bits_per_symbol(&self) -> usize { 1 }
+
+    fn slice(&self, i: f32, q: f32, b: &mut [u8]) {
+        // Project onto the constellation axis.
+        b[0] = (i * self.phase.cos() + q * self.phase.sin() >= 0.0) as u8;
+    }
+}
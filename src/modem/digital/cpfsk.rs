@@ -1,4 +1,5 @@
 use freq::Freq;
+use nco::NCO;
 use rates::Rates;
 
 use super::DigitalPhasor;
@@ -6,8 +7,13 @@ use super::util::bytes_to_bits;
 
 pub struct CPFSK {
     bits_per_symbol: usize,
-    freq: f32,
+    freq: Freq,
     amplitude: f32,
+    /// Oscillator at the current tone, holding the phase at the start of the current
+    /// symbol and retuned for each symbol so the phase stays continuous.
+    nco: NCO,
+    /// Sample the current symbol started on.
+    start: usize,
 }
 
 impl CPFSK {
@@ -16,29 +22,36 @@ impl CPFSK {
     {
         CPFSK {
             bits_per_symbol: bits_per_symbol,
-            freq: Freq::new(deviation * rates.baud_rate / 2,
-                            rates.sample_rate).sample_freq(),
+            freq: Freq::from_hz((deviation * rates.baud_rate) as f64 / 2.0,
+                                rates.sample_rate),
             amplitude: amplitude,
+            nco: NCO::new(Freq::new(0, rates.sample_rate)),
+            start: 0,
         }
     }
 
-    fn coef(&self, symbol: u8) -> f32 {
-        2.0 * symbol as f32
-    }
-
-    fn inner(&self, b: &[u8], s: usize) -> f32 {
-        self.coef(bytes_to_bits(b)) * self.freq * s as f32
+    fn coef(&self, symbol: u16) -> f64 {
+        2.0 * symbol as f64
     }
 }
 
 impl DigitalPhasor for CPFSK {
     fn bits_per_symbol(&self) -> usize { self.bits_per_symbol }
 
-    fn i(&self, s: usize, b: &[u8]) -> f32 {
-        self.amplitude * self.inner(b, s).cos()
+    fn i(&self, s: usize, _: &[u8]) -> f32 {
+        self.amplitude * self.nco.sin_cos_ahead(s - self.start).1
     }
 
-    fn q(&self, s: usize, b: &[u8]) -> f32 {
-        self.amplitude * self.inner(b, s).sin()
+    fn q(&self, s: usize, _: &[u8]) -> f32 {
+        self.amplitude * self.nco.sin_cos_ahead(s - self.start).0
+    }
+
+    fn update(&mut self, s: usize, b: &[u8]) {
+        // Run the previous tone up to this symbol.
+        self.nco.skip(s - self.start);
+        self.start = s;
+
+        let hz = self.coef(bytes_to_bits(b)) * self.freq.hz();
+        self.nco.tune(Freq::from_hz(hz, self.freq.sample_rate()));
     }
 }
@@ -20,7 +20,7 @@ impl DCQPSK {
         }
     }
 
-    fn term(&self, symbol: u8) -> f32 {
+    fn term(&self, symbol: u16) -> f32 {
         const MAP: [f32; 4] = [
             0.0,
             PI / 2.0,
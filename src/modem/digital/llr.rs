@@ -0,0 +1,206 @@
+/// Implements soft-decision demapping, which produces a log-likelihood ratio (LLR) for
+/// each bit of a received symbol rather than a hard 0 or 1. Each LLR is computed as
+/// ln(P(b = 1) / P(b = 0)) assuming additive white gaussian noise, so positive values
+/// favour a 1 bit and the magnitude gives the confidence.
+
+use std;
+
+use super::Slicer;
+use super::util::max_symbol;
+
+/// Floor on the estimated noise variance, so a clean signal doesn't give infinite LLRs.
+const MIN_NOISE_VAR: f32 = 1e-6;
+
+/// Method for combining the per-symbol likelihoods into per-bit likelihoods.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Metric {
+    /// Approximate each sum of likelihoods by its largest term.
+    MaxLog,
+    /// Compute the exact log of each sum of likelihoods.
+    LogSumExp,
+}
+
+pub trait SoftSlicer: Slicer {
+    /// Compute the LLR of each bit, MSB first, for the symbol received at (i, q) into
+    /// `llrs`, which must hold `bits_per_symbol` values. The given noise variance is the
+    /// total variance over both the I and Q components.
+    fn llr(&self, i: f32, q: f32, noise_var: f32, metric: Metric, llrs: &mut [f32]);
+}
+
+/// Compute the bit LLRs for the symbol received at (i, q) in a constellation of the given
+/// symbol size, where `point` gives the (i, q) position of each symbol value.
+pub fn constellation_llr<F>(bits_per_symbol: usize, point: F, i: f32, q: f32,
+                            noise_var: f32, metric: Metric, llrs: &mut [f32])
+    where F: Fn(u16) -> (f32, f32)
+{
+    // Log-likelihood of each symbol, up to a shared constant.
+    let lls: Vec<f32> = (0..max_symbol(bits_per_symbol) + 1).map(|s| {
+        let (pi, pq) = point(s as u16);
+        let (di, dq) = (i - pi, q - pq);
+
+        -(di * di + dq * dq) / noise_var
+    }).collect();
+
+    for (bit, llr) in llrs.iter_mut().enumerate() {
+        let mask = 1 << (bits_per_symbol - 1 - bit);
+
+        let ones = lls.iter().enumerate().filter(|&(s, _)| s & mask != 0);
+        let zeros = lls.iter().enumerate().filter(|&(s, _)| s & mask == 0);
+
+        *llr = combine(ones.map(|(_, &l)| l), metric) -
+               combine(zeros.map(|(_, &l)| l), metric);
+    }
+}
+
+/// Estimates the noise variance of received symbols from their distances to the hard
+/// decisions, averaged over about the given number of recent symbols. Wrong decisions
+/// land nearer than the sent points, so this underestimates heavy noise.
+pub struct NoiseEstimate {
+    var: f32,
+    /// Weight of each new symbol once the average is running.
+    weight: f32,
+    /// Symbols seen, so the first are averaged equally.
+    count: usize,
+}
+
+impl NoiseEstimate {
+    pub fn new(symbols: usize) -> NoiseEstimate {
+        assert!(symbols > 0);
+
+        NoiseEstimate {
+            var: 0.0,
+            weight: 1.0 / symbols as f32,
+            count: 0,
+        }
+    }
+
+    /// Add the distance (i, q) of a received symbol from its hard decision.
+    pub fn add(&mut self, di: f32, dq: f32) {
+        self.count += 1;
+
+        let weight = (1.0 / self.count as f32).max(self.weight);
+        self.var += weight * (di * di + dq * dq - self.var);
+    }
+
+    /// Get the estimated noise variance, over both the I and Q components.
+    pub fn var(&self) -> f32 {
+        self.var.max(MIN_NOISE_VAR)
+    }
+}
+
+/// Combine the given log-likelihoods into the log of their sum.
+fn combine<I: Iterator<Item = f32> + Clone>(lls: I, metric: Metric) -> f32 {
+    let max = lls.clone().fold(std::f32::NEG_INFINITY, f32::max);
+
+    match metric {
+        Metric::MaxLog => max,
+        Metric::LogSumExp => max + lls.map(|l| (l - max).exp()).sum::<f32>().ln(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+
+    use digital::DigitalPhasor;
+    use digital::apsk::{APSK, Ring};
+    use digital::mapping::{Gray, Table};
+    use digital::mpsk::MPSK;
+    use digital::qam::{QAM, Shape, Scale};
+    use digital::util::{bits_to_bytes, max_symbol};
+    use super::{Metric, SoftSlicer, NoiseEstimate, MIN_NOISE_VAR};
+
+    // Verify the LLR signs agree with the hard decision near every symbol.
+    fn check<P: DigitalPhasor + SoftSlicer>(p: P) {
+        let bps = DigitalPhasor::bits_per_symbol(&p);
+
+        let mut bits = vec![0; bps];
+        let mut sliced = vec![0; bps];
+        let mut llrs = vec![0.0; bps];
+
+        for s in 0..max_symbol(bps) + 1 {
+            bits_to_bytes(s as u16, &mut bits[..]);
+            let (i, q) = p.next(0, &bits[..]);
+            p.slice(i, q, &mut sliced[..]);
+
+            for &metric in &[Metric::MaxLog, Metric::LogSumExp] {
+                p.llr(i + 0.01, q - 0.01, 0.1, metric, &mut llrs[..]);
+
+                for (&b, &l) in sliced.iter().zip(llrs.iter()) {
+                    assert_eq!(b == 1, l > 0.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_signs() {
+        check(MPSK::new(3, PI / 8.0, 1.0));
+        check(QAM::new(4, 0.0, 1.0));
+        check(QAM::new(6, PI / 7.0, 2.0));
+        check(APSK::new(1.0, 4, vec![
+            Ring::new(0..4, 0.5, PI / 4.0),
+            Ring::new(4..16, 1.0, PI / 12.0),
+        ]));
+
+        check(MPSK::with_mapping(3, PI / 8.0, 1.0, Gray));
+        check(QAM::with_mapping(4, 0.0, 1.0, Gray));
+        check(QAM::with_mapping(5, PI / 9.0, 4.0, Gray));
+        check(QAM::with_shape(7, Shape::Cross, Scale::AveragePower, 0.0, 4.0, Gray));
+        check(APSK::with_mapping(1.0, 4, vec![
+            Ring::new(0..4, 0.5, PI / 4.0),
+            Ring::new(4..16, 1.0, PI / 12.0),
+        ], Table::dvbs2_16apsk()));
+    }
+
+    #[test]
+    fn test_noise() {
+        let mut n = NoiseEstimate::new(100);
+        assert_eq!(n.var(), MIN_NOISE_VAR);
+
+        // Offsets of 0.1 in each of I and Q.
+        for k in 0..1000 {
+            let s = if k % 2 == 0 { 0.1 } else { -0.1 };
+            n.add(s, -s);
+        }
+
+        assert!((n.var() - 0.02).abs() < 1e-6);
+
+        // Follows a change in the noise.
+        for _ in 0..1000 {
+            n.add(0.2, 0.0);
+        }
+
+        assert!((n.var() - 0.04).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_binary() {
+        // With two antipodal points at ±A, the LLR is exactly 4Ay/N0 in favour of the
+        // point at -A, which carries the 1 bit.
+        let p = MPSK::new(1, 0.0, 2.0);
+        let mut llr = [0.0];
+
+        p.llr(0.3, 0.0, 0.5, Metric::LogSumExp, &mut llr);
+        assert!((llr[0] - -4.0 * 2.0 * 0.3 / 0.5).abs() < 1e-4);
+
+        p.llr(0.3, 0.0, 0.5, Metric::MaxLog, &mut llr);
+        assert!((llr[0] - -4.0 * 2.0 * 0.3 / 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_metrics() {
+        let p = QAM::new(4, 0.0, 1.0);
+
+        let mut exact = [0.0; 4];
+        let mut approx = [0.0; 4];
+
+        // The approximation converges to the exact value at high SNR.
+        p.llr(0.1, -0.2, 0.001, Metric::LogSumExp, &mut exact);
+        p.llr(0.1, -0.2, 0.001, Metric::MaxLog, &mut approx);
+
+        for (e, a) in exact.iter().zip(approx.iter()) {
+            assert!((e - a).abs() / e.abs() < 0.01);
+        }
+    }
+}
@@ -0,0 +1,181 @@
+/// Implements mappings between the bits of a symbol and the index of its constellation
+/// point, so constellations can be labelled such that neighbouring points differ in as
+/// few bits as possible. A symbol error to a neighbour then causes a single bit error.
+///
+/// The constellation phasors index their points in order around each ring for PSK and
+/// APSK, and in order along each of I and Q for QAM, where the mapping applies to the I
+/// and Q bits separately.
+
+use super::qam;
+
+/// Maps between symbol bits and constellation point indexes.
+pub trait Mapping {
+    /// Get the index of the constellation point for the given bits.
+    fn point(&self, bits: u16) -> u16;
+
+    /// Get the bits for the constellation point with the given index.
+    fn bits(&self, point: u16) -> u16;
+
+    /// Get the number of points labelled, or None if the mapping labels any number.
+    fn size(&self) -> Option<usize> { None }
+
+    /// Check if the mapping can label symbols of the given number of bits.
+    fn fits(&self, bits: usize) -> bool {
+        self.size().map_or(true, |n| n == 1 << bits)
+    }
+}
+
+/// Lets a mapping chosen at runtime be used.
+impl Mapping for Box<Mapping> {
+    fn point(&self, bits: u16) -> u16 { (**self).point(bits) }
+    fn bits(&self, point: u16) -> u16 { (**self).bits(point) }
+    fn size(&self) -> Option<usize> { (**self).size() }
+}
+
+/// Labels points in natural binary order.
+pub struct Natural;
+
+impl Mapping for Natural {
+    fn point(&self, bits: u16) -> u16 { bits }
+    fn bits(&self, point: u16) -> u16 { point }
+}
+
+/// Labels points with the reflected binary Gray code, so adjacent points, including
+/// the last and first, differ in one bit.
+pub struct Gray;
+
+impl Mapping for Gray {
+    fn point(&self, bits: u16) -> u16 {
+        let mut point = bits;
+        let mut shift = bits >> 1;
+
+        while shift != 0 {
+            point ^= shift;
+            shift >>= 1;
+        }
+
+        point
+    }
+
+    fn bits(&self, point: u16) -> u16 {
+        point ^ point >> 1
+    }
+}
+
+/// Labels points from a table.
+pub struct Table {
+    /// Point index for each value of bits.
+    points: Vec<u16>,
+    /// Bits for each point index.
+    labels: Vec<u16>,
+}
+
+impl Table {
+    /// Create a mapping where the bits `b` label the point `points[b]`. The table must
+    /// hold each point index once.
+    pub fn new(points: Vec<u16>) -> Table {
+        let mut labels = vec![0; points.len()];
+        let mut seen = vec![false; points.len()];
+
+        for (bits, &p) in points.iter().enumerate() {
+            assert!((p as usize) < points.len() && !seen[p as usize]);
+
+            seen[p as usize] = true;
+            labels[p as usize] = bits as u16;
+        }
+
+        Table {
+            points: points,
+            labels: labels,
+        }
+    }
+
+    /// DVB-S2 16APSK labelling, for an inner ring of 4 points starting at π/4 and an
+    /// outer ring of 12 points starting at π/12.
+    pub fn dvbs2_16apsk() -> Table {
+        Table::new(vec![5, 14, 8, 11, 4, 15, 9, 10, 6, 13, 7, 12, 0, 3, 1, 2])
+    }
+}
+
+impl Mapping for Table {
+    fn point(&self, bits: u16) -> u16 { self.points[bits as usize] }
+    fn bits(&self, point: u16) -> u16 { self.labels[point as usize] }
+    fn size(&self) -> Option<usize> { Some(self.points.len()) }
+}
+
+/// Look up a mapping by name: `natural`, `gray`, or `dvbs2` for 16APSK.
+pub fn parse(name: &str) -> Option<Box<Mapping>> {
+    match name {
+        "natural" => Some(Box::new(Natural)),
+        "gray" => Some(Box::new(Gray)),
+        "dvbs2" => Some(Box::new(Table::dvbs2_16apsk())),
+        _ => None,
+    }
+}
+
+/// Check if the given mapping can label the symbols of the modulation with the given
+/// name, as the binaries call them. A table labels a fixed number of points, and QAM
+/// labels the I and Q levels separately. Modulations without a mapping fit any.
+pub fn fits_modulation(mapping: &Mapping, name: &str) -> bool {
+    match name {
+        "16psk" | "16apsk" => mapping.fits(4),
+        _ => qam::parse_bits(name).map_or(true, |b| {
+            mapping.fits((b + 1) / 2) && mapping.fits(b / 2)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Mapping, Natural, Gray, Table, fits_modulation};
+
+    // Verify the mapping is a bijection over the given number of bits.
+    fn check<M: Mapping>(m: &M, bits_per_symbol: usize) {
+        for b in 0..1usize << bits_per_symbol {
+            assert!((m.point(b as u16) as usize) < 1 << bits_per_symbol);
+            assert_eq!(m.bits(m.point(b as u16)), b as u16);
+        }
+    }
+
+    #[test]
+    fn test_gray() {
+        check(&Natural, 4);
+
+        for bps in 1..13 {
+            check(&Gray, bps);
+
+            let n = 1u16 << bps;
+
+            for p in 0..n {
+                let next = (p + 1) % n;
+                assert_eq!((Gray.bits(p) ^ Gray.bits(next)).count_ones(), 1);
+            }
+        }
+
+        assert_eq!(Gray.bits(0b110), 0b101);
+        assert_eq!(Gray.point(0b101), 0b110);
+    }
+
+    #[test]
+    fn test_table() {
+        let t = Table::dvbs2_16apsk();
+        check(&t, 4);
+
+        // Neighbours around the outer ring differ in one bit.
+        for p in 4..16 {
+            let next = if p == 15 { 4 } else { p + 1 };
+            assert_eq!((t.bits(p) ^ t.bits(next)).count_ones(), 1);
+        }
+
+        assert_eq!(t.bits(0), 0b1100);
+        assert_eq!(t.point(0b0000), 5);
+
+        // A table only fits its own size, unlike the computed mappings.
+        assert!(t.fits(4) && !t.fits(3) && !t.fits(6));
+        assert!(Gray.fits(6) && Natural.fits(3));
+
+        assert!(fits_modulation(&t, "16apsk") && fits_modulation(&t, "qam256"));
+        assert!(!fits_modulation(&t, "qam16") && !fits_modulation(&t, "qam512"));
+        assert!(fits_modulation(&Gray, "qam32") && fits_modulation(&t, "bpsk"));
+    }
+}
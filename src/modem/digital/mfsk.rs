@@ -1,11 +1,11 @@
 use freq::Freq;
-use util::mod_trig;
+use nco::NCO;
 
 use super::DigitalPhasor;
 use super::util::{bytes_to_bits, max_symbol};
 
 pub trait SymbolMap {
-    fn coef(&self, symbol: u8) -> f32;
+    fn coef(&self, symbol: u16) -> f32;
 }
 
 pub struct DefaultMap {
@@ -21,7 +21,7 @@ impl DefaultMap {
 }
 
 impl SymbolMap for DefaultMap {
-    fn coef(&self, symbol: u8) -> f32 {
+    fn coef(&self, symbol: u16) -> f32 {
         (2 * symbol as i32 - self.max_symbol) as f32
     }
 }
@@ -29,18 +29,21 @@ impl SymbolMap for DefaultMap {
 pub struct IncreaseMap;
 
 impl SymbolMap for IncreaseMap {
-    fn coef(&self, symbol: u8) -> f32 {
+    fn coef(&self, symbol: u16) -> f32 {
         (2 * symbol) as f32
     }
 }
 
 pub struct MFSK<M: SymbolMap> {
     bits_per_symbol: usize,
-    deviation: f32,
+    deviation: Freq,
     amplitude: f32,
     map: M,
-    phase_offset: f32,
-    cur_coef: f32,
+    /// Oscillator at the current tone, holding the phase at the start of the current
+    /// symbol and retuned for each symbol so the phase stays continuous.
+    nco: NCO,
+    /// Sample the current symbol started on.
+    start: usize,
 }
 
 impl<M: SymbolMap> MFSK<M> {
@@ -49,36 +52,32 @@ impl<M: SymbolMap> MFSK<M> {
     {
         MFSK {
             bits_per_symbol: bits_per_symbol,
-            deviation: deviation.sample_freq(),
+            deviation: deviation,
             amplitude: amplitude,
             map: map,
-            phase_offset: 0.0,
-            cur_coef: 0.0,
+            nco: NCO::new(Freq::new(0, deviation.sample_rate())),
+            start: 0,
         }
     }
-
-    fn inner(&self, s: usize) -> f32 {
-        self.cur_coef * self.deviation * s as f32 + self.phase_offset
-    }
 }
 
 impl<M: SymbolMap> DigitalPhasor for MFSK<M> {
     fn bits_per_symbol(&self) -> usize { self.bits_per_symbol }
 
     fn update(&mut self, s: usize, b: &[u8]) {
-        let next_coef = self.map.coef(bytes_to_bits(b));
-
-        self.phase_offset += (self.cur_coef - next_coef) * self.deviation * s as f32;
-        self.phase_offset = mod_trig(self.phase_offset);
+        // Run the previous tone up to this symbol.
+        self.nco.skip(s - self.start);
+        self.start = s;
 
-        self.cur_coef = next_coef;
+        let hz = self.map.coef(bytes_to_bits(b)) as f64 * self.deviation.hz();
+        self.nco.tune(Freq::from_hz(hz, self.deviation.sample_rate()));
     }
 
     fn i(&self, s: usize, _: &[u8]) -> f32 {
-        self.amplitude * self.inner(s).cos()
+        self.amplitude * self.nco.sin_cos_ahead(s - self.start).1
     }
 
     fn q(&self, s: usize, _: &[u8]) -> f32 {
-        self.amplitude * self.inner(s).sin()
+        self.amplitude * self.nco.sin_cos_ahead(s - self.start).0
     }
 }
@@ -1,4 +1,5 @@
 mod phasor;
+mod slicer;
 mod util;
 
 pub mod apsk;
@@ -8,6 +9,8 @@ pub mod bpsk;
 pub mod cpfsk;
 pub mod dcqpsk;
 pub mod dmpsk;
+pub mod llr;
+pub mod mapping;
 pub mod mfsk;
 pub mod mpsk;
 pub mod msk;
@@ -16,3 +19,4 @@ pub mod qam;
 pub mod qpsk;
 
 pub use self::phasor::DigitalPhasor;
+pub use self::slicer::Slicer;
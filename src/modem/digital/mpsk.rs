@@ -1,22 +1,40 @@
 use std::f32::consts::PI;
 
-use super::DigitalPhasor;
-use super::util::bytes_to_bits;
+use util::mod_trig;
 
-pub struct MPSK {
+use super::{DigitalPhasor, Slicer};
+use super::llr::{constellation_llr, Metric, SoftSlicer};
+use super::mapping::{Mapping, Natural};
+use super::util::{bits_to_bytes, bytes_to_bits};
+
+pub struct MPSK<M: Mapping = Natural> {
     bits_per_symbol: usize,
     num_symbols: f32,
     amplitude: f32,
     phase_offset: f32,
+    map: M,
 }
 
 impl MPSK {
     pub fn new(bits_per_symbol: usize, phase_offset: f32, amplitude: f32) -> MPSK {
+        MPSK::with_mapping(bits_per_symbol, phase_offset, amplitude, Natural)
+    }
+}
+
+impl<M: Mapping> MPSK<M> {
+    /// Create a new MPSK phasor with the given labelling of the points, in order of
+    /// increasing phase.
+    pub fn with_mapping(bits_per_symbol: usize, phase_offset: f32, amplitude: f32, map: M)
+        -> MPSK<M>
+    {
+        assert!(map.fits(bits_per_symbol), "mapping doesn't fit the constellation");
+
         MPSK {
             bits_per_symbol: bits_per_symbol,
             num_symbols: (1 << bits_per_symbol) as f32,
             amplitude: amplitude,
             phase_offset: phase_offset,
+            map: map,
         }
     }
 
@@ -25,11 +43,15 @@ impl MPSK {
     }
 
     fn phase(&self, b: &[u8]) -> f32 {
-        2.0 * PI * bytes_to_bits(b) as f32 / self.num_symbols
+        self.phase_symbol(self.map.point(bytes_to_bits(b)))
+    }
+
+    fn phase_symbol(&self, s: u16) -> f32 {
+        2.0 * PI * s as f32 / self.num_symbols
     }
 }
 
-impl DigitalPhasor for MPSK {
+impl<M: Mapping> DigitalPhasor for MPSK<M> {
     fn bits_per_symbol(&self) -> usize { self.bits_per_symbol }
 
     fn i(&self, _: usize, b: &[u8]) -> f32 {
@@ -41,10 +63,31 @@ impl DigitalPhasor for MPSK {
     }
 }
 
+impl<M: Mapping> Slicer for MPSK<M> {
+    fn bits_per_symbol(&self) -> usize { self.bits_per_symbol }
+
+    fn slice(&self, i: f32, q: f32, b: &mut [u8]) {
+        let phase = mod_trig(q.atan2(i) - self.phase_offset);
+        let symbol = (phase * self.num_symbols / (2.0 * PI)).round() % self.num_symbols;
+
+        bits_to_bytes(self.map.bits(symbol as u16), b);
+    }
+}
+
+impl<M: Mapping> SoftSlicer for MPSK<M> {
+    fn llr(&self, i: f32, q: f32, noise_var: f32, metric: Metric, llrs: &mut [f32]) {
+        constellation_llr(self.bits_per_symbol, |s| {
+            let inner = self.phase_symbol(self.map.point(s)) + self.phase_offset;
+            (self.amplitude * inner.cos(), self.amplitude * inner.sin())
+        }, i, q, noise_var, metric, llrs);
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::MPSK;
-    use digital::DigitalPhasor;
+    use digital::{DigitalPhasor, Slicer};
+    use digital::mapping::Gray;
 
     #[test]
     fn test_mpsk() {
@@ -61,4 +104,18 @@ mod test {
         assert!(mpsk.i(0, &[1, 1]).abs() < 0.001);
         assert_eq!(mpsk.q(0, &[1, 1]), -1.0);
     }
+
+    #[test]
+    fn test_gray() {
+        let mpsk = MPSK::with_mapping(2, 0.0, 1.0, Gray);
+
+        // The third point, at π, is labelled 11.
+        assert_eq!(mpsk.i(0, &[1, 1]), -1.0);
+        assert!(mpsk.i(0, &[1, 0]).abs() < 0.001);
+        assert_eq!(mpsk.q(0, &[1, 0]), -1.0);
+
+        let mut b = [0; 2];
+        mpsk.slice(-0.9, 0.2, &mut b);
+        assert_eq!(b, [1, 1]);
+    }
 }
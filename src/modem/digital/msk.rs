@@ -1,36 +1,42 @@
 use std::f32::consts::PI;
 
+use freq::Freq;
+use nco::NCO;
+
 use super::DigitalPhasor;
 use super::util::bit_to_sign;
 
 pub struct MSK {
     amplitude: f32,
-    samples_per_bit: usize,
+    /// Oscillator advancing a quarter cycle per bit, evaluated at each sample.
+    nco: NCO,
 }
 
 impl MSK {
-    pub fn new(amplitude: f32, samples_per_symbol: usize) -> MSK {
-        assert!(samples_per_symbol % 2 == 0);
+    /// Create a new MSK phasor with the given, possibly fractional, samples per symbol.
+    pub fn new(amplitude: f32, samples_per_symbol: f32) -> MSK {
+        let mut nco = NCO::new(Freq::new(0, 1));
+        nco.set_freq(PI / samples_per_symbol);
 
         MSK {
             amplitude: amplitude,
-            samples_per_bit: samples_per_symbol / 2,
+            nco: nco,
         }
     }
-
-    fn inner(&self, s: usize) -> f32 {
-        PI / 2.0 * s as f32 / self.samples_per_bit as f32
-    }
 }
 
 impl DigitalPhasor for MSK {
     fn bits_per_symbol(&self) -> usize { 2 }
 
+    // Samples count from 1, and each bit changes where its weight crosses zero, the
+    // even bit at the start of a symbol and the odd bit half a symbol later, so the
+    // phase stays continuous.
+
     fn i(&self, s: usize, b: &[u8]) -> f32 {
-        self.amplitude * bit_to_sign(b[0]) * self.inner(s).cos()
+        self.amplitude * bit_to_sign(b[0]) * self.nco.sin_cos_ahead(s - 1).0
     }
 
     fn q(&self, s: usize, b: &[u8]) -> f32 {
-        -self.amplitude * bit_to_sign(b[1]) * self.inner(s).sin()
+        -self.amplitude * bit_to_sign(b[1]) * self.nco.sin_cos_ahead(s - 1).1
     }
 }
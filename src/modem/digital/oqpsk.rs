@@ -1,4 +1,4 @@
-use super::DigitalPhasor;
+use super::{DigitalPhasor, Slicer};
 use super::util::bit_to_sign;
 
 pub struct OQPSK {
@@ -24,3 +24,12 @@ impl DigitalPhasor for OQPSK {
         bit_to_sign(b[1]) * self.amplitude
     }
 }
+
+impl Slicer for OQPSK {
+    fn bits_per_symbol(&self) -> usize { 2 }
+
+    fn slice(&self, i: f32, q: f32, b: &mut [u8]) {
+        b[0] = (i >= 0.0) as u8;
+        b[1] = (q >= 0.0) as u8;
+    }
+}
@@ -1,8 +1,13 @@
 pub trait DigitalPhasor {
     fn bits_per_symbol(&self) -> usize;
 
+    /// Start a new symbol with the given bits at the given sample.
     fn update(&mut self, _s: usize, _b: &[u8]) {}
 
+    /// Get the in-phase and quadrature components of the given symbol bits at the given
+    /// sample. Phasors that carry phase across symbols, such as continuous-phase FSK,
+    /// only give valid components for the symbol last passed to `update` and for samples
+    /// from its start on, so they must be read through `next` as the modulator does.
     fn i(&self, s: usize, b: &[u8]) -> f32;
     fn q(&self, s: usize, b: &[u8]) -> f32;
 
@@ -10,3 +15,100 @@ pub trait DigitalPhasor {
         (self.i(s, b), self.q(s, b))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+
+    use carrier::Carrier;
+    use data::{Bits, EvenOddOffset, Source};
+    use digital::DigitalPhasor;
+    use digital::bfsk::BFSK;
+    use digital::cpfsk::CPFSK;
+    use digital::mfsk::{MFSK, DefaultMap, IncreaseMap};
+    use digital::msk::MSK;
+    use digital::util::bytes_to_bits;
+    use freq::Freq;
+    use modulator::DigitalModulator;
+    use rates::Rates;
+
+    const BITS: &'static [u8] = &[0, 1, 1, 0, 1, 1, 0, 0, 0, 1, 1, 1, 0, 1, 0, 0];
+    const SPS: usize = 40;
+
+    fn rates() -> Rates {
+        Rates::new(100, 100 * SPS)
+    }
+
+    // Modulate the bits from the given source at baseband, verify the amplitude stays
+    // constant, and get the phase advance (radians) from each sample to the next.
+    fn advances(phasor: Box<DigitalPhasor>, src: Box<Source>) -> Vec<f32> {
+        let mut carrier = Carrier::new(Freq::new(0, rates().sample_rate));
+        let out: Vec<(f32, f32)> = DigitalModulator::new(&mut carrier, phasor, src)
+            .map(|s| (s.i, s.q)).collect();
+
+        for &(i, q) in out.iter() {
+            assert!(((i * i + q * q).sqrt() - 1.0).abs() < 1e-4);
+        }
+
+        out.windows(2).map(|w| {
+            let ((i0, q0), (i1, q1)) = (w[0], w[1]);
+            (q1 * i0 - i1 * q0).atan2(i1 * i0 + q1 * q0)
+        }).collect()
+    }
+
+    // Verify each sample advances at the tone (radians/sample) of its own symbol, so
+    // the phase carries over every symbol boundary without a jump.
+    fn check<F: Fn(u16) -> f32>(phasor: Box<DigitalPhasor>, tone: F) {
+        let bps = phasor.bits_per_symbol();
+        let adv = advances(phasor, Box::new(Bits::new(BITS, rates(), bps)));
+
+        assert_eq!(adv.len(), BITS.len() / bps * SPS - 1);
+
+        for (n, &a) in adv.iter().enumerate() {
+            let sym = bytes_to_bits(&BITS[n / SPS * bps..][..bps]);
+            assert!((a - tone(sym)).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_bfsk() {
+        check(Box::new(BFSK::new(Freq::new(500, 4000), 1.0)),
+              |b| b as f32 * PI / 4.0);
+    }
+
+    #[test]
+    fn test_mfsk() {
+        check(Box::new(MFSK::new(2, Freq::new(250, 4000), 1.0, IncreaseMap)),
+              |s| s as f32 * PI / 4.0);
+        check(Box::new(MFSK::new(2, Freq::new(250, 4000), 1.0, DefaultMap::new(2))),
+              |s| (2.0 * s as f32 - 3.0) * PI / 8.0);
+        check(Box::new(MFSK::new(4, Freq::new(50, 4000), 1.0, IncreaseMap)),
+              |s| s as f32 * PI / 20.0);
+    }
+
+    #[test]
+    fn test_cpfsk() {
+        // Tones are spaced by the deviation times the baud rate.
+        check(Box::new(CPFSK::new(2, rates(), 1.0, 1)), |s| s as f32 * PI / 20.0);
+        check(Box::new(CPFSK::new(2, rates(), 1.0, 3)), |s| s as f32 * PI * 3.0 / 20.0);
+    }
+
+    #[test]
+    fn test_msk() {
+        let src = EvenOddOffset::new(Bits::new(BITS, rates(), 2), rates(), 2);
+        let adv = advances(Box::new(MSK::new(1.0, SPS as f32)), Box::new(src));
+
+        assert_eq!(adv.len(), BITS.len() / 2 * SPS - 1);
+
+        for (n, &a) in adv.iter().enumerate() {
+            // The even bit changes each symbol and the odd bit half a symbol later,
+            // starting from 0.
+            let even = BITS[n / SPS * 2];
+            let odd = if n < SPS / 2 { 0 } else { BITS[(n - SPS / 2) / SPS * 2 + 1] };
+
+            // Matching bits give the upper tone, a quarter cycle per half symbol.
+            let tone = if even == odd { PI } else { -PI } / SPS as f32;
+            assert!((a - tone).abs() < 1e-4);
+        }
+    }
+}
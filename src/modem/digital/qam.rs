@@ -1,69 +1,261 @@
-use super::DigitalPhasor;
-use super::util::{bytes_to_bits, max_symbol};
+use std;
 
-pub struct QAM {
+use super::{DigitalPhasor, Slicer};
+use super::llr::{constellation_llr, Metric, SoftSlicer};
+use super::mapping::{Mapping, Natural};
+use super::util::{bits_to_bytes, bytes_to_bits, max_symbol};
+
+/// Layout of the constellation points.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Shape {
+    /// Grid of points, with I taking the extra bit for an odd number of bits, so square
+    /// for an even number.
+    Rectangular,
+    /// Square with the corners cut off, for an odd number of bits from 5. The points
+    /// outside the square of the rectangle are folded into the space above and below it,
+    /// which takes less peak and average power.
+    Cross,
+}
+
+impl Shape {
+    /// Get the usual shape for the given number of bits: cross for an odd number from 5,
+    /// and rectangular otherwise.
+    pub fn default_for(bits_per_symbol: usize) -> Shape {
+        if bits_per_symbol % 2 == 1 && bits_per_symbol >= 5 {
+            Shape::Cross
+        } else {
+            Shape::Rectangular
+        }
+    }
+}
+
+/// Scaling of the constellation to the given amplitude. The modulate and demodulate
+/// binaries always use peak scaling, which keeps the waveform within the amplitude, so
+/// average power scaling is only available to library users.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Scale {
+    /// Put the outermost I and Q levels at ±amplitude/2.
+    Peak,
+    /// Make the average power over all points the square of the amplitude, so an
+    /// amplitude of 1 gives unit average power.
+    AveragePower,
+}
+
+/// Parse a modulation name, `qam4` to `qam4096`, into its bits per symbol.
+pub fn parse_bits(name: &str) -> Option<usize> {
+    if !name.starts_with("qam") {
+        return None;
+    }
+
+    match name[3..].parse::<usize>() {
+        Ok(m) if m.is_power_of_two() && m >= 4 && m <= 4096 => {
+            Some(m.trailing_zeros() as usize)
+        },
+        _ => None,
+    }
+}
+
+pub struct QAM<M: Mapping = Natural> {
     bits_per_symbol: usize,
-    /// Number of bits in each I and Q.
-    bits_per_carrier: usize,
-    max_symbol: f32,
+    /// Number of bits in I, the MSBs, and Q, the LSBs.
+    bits_i: usize,
+    bits_q: usize,
+    shape: Shape,
+    /// Rotated and scaled (i, q) position of each symbol value.
+    points: Vec<(f32, f32)>,
     phase_cos: f32,
     phase_sin: f32,
-    amplitude: f32,
+    /// Scale from the grid of odd integers to the output.
+    scale: f32,
+    map: M,
 }
 
 impl QAM {
     pub fn new(bits_per_symbol: usize, phase: f32, amplitude: f32) -> QAM {
+        QAM::with_mapping(bits_per_symbol, phase, amplitude, Natural)
+    }
+}
+
+impl<M: Mapping> QAM<M> {
+    /// Create a new QAM phasor with the given labelling of the I and Q levels, each in
+    /// order of increasing level, which must fit the levels on both axes.
+    pub fn with_mapping(bits_per_symbol: usize, phase: f32, amplitude: f32, map: M)
+        -> QAM<M>
+    {
+        QAM::with_shape(bits_per_symbol, Shape::default_for(bits_per_symbol), Scale::Peak,
+                        phase, amplitude, map)
+    }
+
+    /// Create a new QAM phasor of 2 to 12 bits per symbol with the given shape and
+    /// scaling. Cross constellations fold points labelled on the rectangle, so the
+    /// mapping is only Gray within the square.
+    pub fn with_shape(bits_per_symbol: usize, shape: Shape, scale: Scale, phase: f32,
+                      amplitude: f32, map: M)
+        -> QAM<M>
+    {
         // Must have a bit for i and a bit for q.
-        assert!(bits_per_symbol > 1);
+        assert!(bits_per_symbol > 1 && bits_per_symbol <= 12);
+
+        if shape == Shape::Cross {
+            assert!(bits_per_symbol % 2 == 1 && bits_per_symbol >= 5);
+        }
 
-        let cs = bits_per_symbol / 2;
-        let ms = max_symbol(cs) as f32;
+        // The mapping labels the I and Q levels separately.
+        assert!(map.fits((bits_per_symbol + 1) / 2) && map.fits(bits_per_symbol / 2),
+                "mapping doesn't fit the constellation");
 
-        QAM {
+        let mut qam = QAM {
             bits_per_symbol: bits_per_symbol,
-            bits_per_carrier: cs,
-            max_symbol: ms,
+            bits_i: (bits_per_symbol + 1) / 2,
+            bits_q: bits_per_symbol / 2,
+            shape: shape,
+            points: vec![],
             phase_cos: phase.cos(),
             phase_sin: phase.sin(),
-            amplitude: amplitude / ms / 2.0,
-        }
+            scale: 1.0,
+            map: map,
+        };
+
+        let grid: Vec<(f32, f32)> = (0..max_symbol(bits_per_symbol) + 1).map(|s| {
+            qam.grid(s as u16)
+        }).collect();
+
+        qam.scale = match scale {
+            Scale::Peak => {
+                let peak = grid.iter().fold(0.0, |m: f32, &(x, y)| {
+                    m.max(x.abs()).max(y.abs())
+                });
+
+                amplitude / peak / 2.0
+            },
+            Scale::AveragePower => {
+                let power = grid.iter().map(|&(x, y)| x * x + y * y).sum::<f32>() /
+                            grid.len() as f32;
+                amplitude / power.sqrt()
+            },
+        };
+
+        qam.points = grid.iter().map(|&(x, y)| {
+            (qam.scale * (x * qam.phase_cos - y * qam.phase_sin),
+             qam.scale * (y * qam.phase_cos + x * qam.phase_sin))
+        }).collect();
+
+        qam
+    }
+
+    /// Compute the unscaled and unrotated position of the given symbol, on the grid of
+    /// odd integers.
+    fn grid(&self, s: u16) -> (f32, f32) {
+        let x = level(self.map.point(s >> self.bits_q), self.bits_i);
+        let y = level(self.map.point(s & max_symbol(self.bits_q) as u16), self.bits_q);
+
+        let (x, y) = match self.shape {
+            Shape::Rectangular => (x, y),
+            Shape::Cross => fold(x, y, 1 << self.bits_q),
+        };
+
+        (x as f32, y as f32)
     }
 
-    fn pos_symbol(&self, s: u8) -> f32 {
-        2.0 * s as f32 - self.max_symbol
+    /// Compute the (i, q) position of the given symbol.
+    fn point(&self, s: u16) -> (f32, f32) {
+        self.points[s as usize]
     }
 
-    fn pos_bytes(&self, b: &[u8]) -> f32 {
-        self.pos_symbol(bytes_to_bits(b))
+    /// Find the index of the level nearest the given unscaled position, on an axis with
+    /// the given number of bits.
+    fn nearest_level(&self, pos: f32, bits: usize) -> u16 {
+        let max = max_symbol(bits) as f32;
+        let s = ((pos + max) / 2.0).round();
+
+        if s < 0.0 {
+            0
+        } else if s > max {
+            max as u16
+        } else {
+            s as u16
+        }
+    }
+}
+
+/// Get the position of the level with the given index, on the grid of odd integers
+/// centered on zero.
+fn level(idx: u16, bits: usize) -> i32 {
+    2 * idx as i32 - max_symbol(bits) as i32
+}
+
+/// Fold a point of the 2m×m rectangle that lies outside the central 3m/2 square into the
+/// band above or below the rectangle, keeping its quadrant. Each m/4 wide strip at the
+/// side is turned on its side, so its upper half lands above and lower half below.
+fn fold(x: i32, y: i32, m: i32) -> (i32, i32) {
+    // Largest level in the square.
+    let edge = 3 * m / 2 - 1;
+
+    if x.abs() <= edge {
+        return (x, y);
     }
+
+    let col = (x.abs() - edge - 2) / 2;
+    let row = (y.abs() - 1) / 2;
+
+    (x.signum() * (2 * row + 1), y.signum() * (m + 1 + 2 * col))
 }
 
-impl DigitalPhasor for QAM {
+impl<M: Mapping> DigitalPhasor for QAM<M> {
     fn bits_per_symbol(&self) -> usize { self.bits_per_symbol }
 
     fn i(&self, _: usize, b: &[u8]) -> f32 {
-        let (msb, lsb) = b.split_at(self.bits_per_carrier);
-
-        self.amplitude * (
-            self.pos_bytes(msb) * self.phase_cos -
-            self.pos_bytes(lsb) * self.phase_sin
-        )
+        self.point(bytes_to_bits(b)).0
     }
 
     fn q(&self, _: usize, b: &[u8]) -> f32 {
-        let (msb, lsb) = b.split_at(self.bits_per_carrier);
+        self.point(bytes_to_bits(b)).1
+    }
+}
+
+impl<M: Mapping> Slicer for QAM<M> {
+    fn bits_per_symbol(&self) -> usize { self.bits_per_symbol }
+
+    fn slice(&self, i: f32, q: f32, b: &mut [u8]) {
+        if self.shape == Shape::Cross {
+            // The folded points don't form a grid, so just search every symbol.
+            let mut best = (0, std::f32::INFINITY);
+
+            for (s, &(pi, pq)) in self.points.iter().enumerate() {
+                let dist = (i - pi) * (i - pi) + (q - pq) * (q - pq);
+
+                if dist < best.1 {
+                    best = (s, dist);
+                }
+            }
+
+            bits_to_bytes(best.0 as u16, b);
+            return;
+        }
+
+        // Undo the constellation rotation to recover the I and Q levels.
+        let pi = (i * self.phase_cos + q * self.phase_sin) / self.scale;
+        let pq = (q * self.phase_cos - i * self.phase_sin) / self.scale;
 
-        self.amplitude * (
-            self.pos_bytes(lsb) * self.phase_cos +
-            self.pos_bytes(msb) * self.phase_sin
-        )
+        let (msb, lsb) = b.split_at_mut(self.bits_i);
+
+        bits_to_bytes(self.map.bits(self.nearest_level(pi, self.bits_i)), msb);
+        bits_to_bytes(self.map.bits(self.nearest_level(pq, self.bits_q)), lsb);
+    }
+}
+
+impl<M: Mapping> SoftSlicer for QAM<M> {
+    fn llr(&self, i: f32, q: f32, noise_var: f32, metric: Metric, llrs: &mut [f32]) {
+        constellation_llr(self.bits_per_symbol, |s| self.point(s), i, q, noise_var,
+                          metric, llrs);
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::QAM;
-    use digital::DigitalPhasor;
+    use super::{QAM, Shape, Scale, parse_bits};
+    use digital::{DigitalPhasor, Slicer};
+    use digital::mapping::{Gray, Natural};
 
     #[test]
     fn test_qam() {
@@ -82,4 +274,88 @@ mod test {
         assert_eq!(qam.i(0, &[1,1,1,1]), 3.0);
         assert_eq!(qam.q(0, &[1,1,1,1]), 3.0);
     }
+
+    #[test]
+    fn test_gray() {
+        let qam = QAM::with_mapping(4, 0.0, 6.0, Gray);
+
+        // Levels -3, -1, 1, 3 are labelled 00, 01, 11, 10.
+        assert_eq!(qam.i(0, &[1,1,1,0]), 1.0);
+        assert_eq!(qam.q(0, &[1,1,1,0]), 3.0);
+
+        assert_eq!(qam.i(0, &[1,0,0,1]), 3.0);
+        assert_eq!(qam.q(0, &[1,0,0,1]), -1.0);
+
+        let mut b = [0; 4];
+        qam.slice(0.9, -3.2, &mut b);
+        assert_eq!(b, [1,1,0,0]);
+    }
+
+    #[test]
+    fn test_shapes() {
+        // With the amplitude at the root of the known average energies, the points land
+        // on the grid of odd integers.
+        for &(bps, shape, energy, edge) in &[(3, Shape::Rectangular, 6.0, 3.0),
+                                             (4, Shape::Rectangular, 10.0, 3.0),
+                                             (5, Shape::Cross, 20.0, 5.0),
+                                             (5, Shape::Rectangular, 26.0, 7.0),
+                                             (6, Shape::Rectangular, 42.0, 7.0),
+                                             (7, Shape::Cross, 82.0, 11.0),
+                                             (9, Shape::Cross, 330.0, 23.0)]
+        {
+            let qam = QAM::with_shape(bps, shape, Scale::AveragePower, 0.0,
+                                      (energy as f32).sqrt(), Natural);
+
+            let mut seen = vec![];
+
+            for &(i, q) in &qam.points {
+                let (x, y) = (i.round(), q.round());
+
+                assert!((i - x).abs() < 1e-3 && (q - y).abs() < 1e-3);
+                assert!(x.abs() % 2.0 == 1.0 && y.abs() % 2.0 == 1.0);
+                assert!(x.abs() <= edge && y.abs() <= edge);
+
+                seen.push((x as i32, y as i32));
+            }
+
+            seen.sort();
+            seen.dedup();
+            assert_eq!(seen.len(), 1 << bps);
+        }
+
+        assert_eq!(Shape::default_for(7), Shape::Cross);
+        assert_eq!(Shape::default_for(3), Shape::Rectangular);
+        assert_eq!(Shape::default_for(12), Shape::Rectangular);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_bits("qam4"), Some(2));
+        assert_eq!(parse_bits("qam128"), Some(7));
+        assert_eq!(parse_bits("qam4096"), Some(12));
+        assert_eq!(parse_bits("qam8192"), None);
+        assert_eq!(parse_bits("qam2"), None);
+        assert_eq!(parse_bits("qam48"), None);
+        assert_eq!(parse_bits("qpsk"), None);
+    }
+
+    #[test]
+    fn test_power() {
+        for bps in 2..13 {
+            let qam = QAM::with_shape(bps, Shape::default_for(bps), Scale::AveragePower,
+                                      0.3, 1.0, Gray);
+
+            let power = qam.points.iter().map(|&(i, q)| i * i + q * q).sum::<f32>() /
+                        qam.points.len() as f32;
+            assert!((power - 1.0).abs() < 1e-3);
+
+            // Peak scaling keeps every point within the amplitude.
+            let qam = QAM::with_shape(bps, Shape::default_for(bps), Scale::Peak, 0.0,
+                                      2.0, Gray);
+
+            for &(i, q) in &qam.points {
+                assert!(i.abs() <= 1.0 + 1e-6 && q.abs() <= 1.0 + 1e-6);
+            }
+        }
+    }
 }
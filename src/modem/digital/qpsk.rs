@@ -1,4 +1,4 @@
-use super::DigitalPhasor;
+use super::{DigitalPhasor, Slicer};
 use super::util::bit_to_sign;
 
 pub struct QPSK {
@@ -34,3 +34,13 @@ impl DigitalPhasor for QPSK {
         )
     }
 }
+
+impl Slicer for QPSK {
+    fn bits_per_symbol(&self) -> usize { 2 }
+
+    fn slice(&self, i: f32, q: f32, b: &mut [u8]) {
+        // Undo the constellation rotation, then each bit is the sign of one axis.
+        b[0] = (i * self.phase_cos + q * self.phase_sin >= 0.0) as u8;
+        b[1] = (q * self.phase_cos - i * self.phase_sin >= 0.0) as u8;
+    }
+}
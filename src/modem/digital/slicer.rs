@@ -0,0 +1,71 @@
+/// Hard-decision demapping of received constellation points back to symbol bits.
+pub trait Slicer {
+    fn bits_per_symbol(&self) -> usize;
+
+    /// Find the constellation point nearest to the given (i, q) and write its bits, one
+    /// bit per byte in MSB-first order, into `b`, which must hold `bits_per_symbol` bits.
+    fn slice(&self, i: f32, q: f32, b: &mut [u8]);
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+
+    use digital::{DigitalPhasor, Slicer};
+    use digital::apsk::{APSK, Ring};
+    use digital::bask::BASK;
+    use digital::bpsk::BPSK;
+    use digital::mpsk::MPSK;
+    use digital::oqpsk::OQPSK;
+    use digital::mapping::{Gray, Table};
+    use digital::qam::{QAM, Shape, Scale};
+    use digital::qpsk::QPSK;
+    use digital::util::{bits_to_bytes, max_symbol};
+
+    // Modulate every symbol, perturb it slightly, and verify it slices back to itself.
+    fn check<P: DigitalPhasor + Slicer>(p: P) {
+        let bps = DigitalPhasor::bits_per_symbol(&p);
+        assert_eq!(Slicer::bits_per_symbol(&p), bps);
+
+        let mut bits = vec![0; bps];
+        let mut sliced = vec![0; bps];
+
+        for s in 0..max_symbol(bps) + 1 {
+            bits_to_bytes(s as u16, &mut bits[..]);
+            let (i, q) = p.next(0, &bits[..]);
+
+            p.slice(i + 0.01, q - 0.01, &mut sliced[..]);
+            assert_eq!(sliced, bits);
+        }
+    }
+
+    #[test]
+    fn test_slicers() {
+        check(BASK::new(1.0));
+        check(BPSK::new(PI / 4.0, 1.0));
+        check(QPSK::new(PI / 3.0, 1.0));
+        check(OQPSK::new(1.0));
+        check(MPSK::new(3, PI / 8.0, 1.0));
+        check(MPSK::new(4, 0.0, 1.0));
+        check(QAM::new(4, 0.0, 1.0));
+        check(QAM::new(6, PI / 7.0, 2.0));
+        check(APSK::new(1.0, 4, vec![
+            Ring::new(0..4, 0.5, PI / 4.0),
+            Ring::new(4..16, 1.0, PI / 12.0),
+        ]));
+
+        check(MPSK::with_mapping(3, PI / 8.0, 1.0, Gray));
+        check(QAM::with_mapping(6, PI / 7.0, 2.0, Gray));
+
+        for bps in 2..13 {
+            check(QAM::with_shape(bps, Shape::default_for(bps), Scale::AveragePower,
+                                  PI / 9.0, 1.0, Gray));
+        }
+
+        check(QAM::with_shape(5, Shape::Rectangular, Scale::Peak, 0.0, 1.0, Gray));
+        check(APSK::with_mapping(1.0, 4, vec![
+            Ring::new(0..4, 0.5, PI / 4.0),
+            Ring::new(4..16, 1.0, PI / 12.0),
+        ], Table::dvbs2_16apsk()));
+    }
+}
@@ -2,21 +2,32 @@ pub fn bit_to_sign(b: u8) -> f32 {
     (2 * b as i8 - 1) as f32
 }
 
-pub fn bytes_to_bits(bytes: &[u8]) -> u8 {
+/// Pack the given bits, one per byte, MSB first into a symbol of up to 16 bits.
+pub fn bytes_to_bits(bytes: &[u8]) -> u16 {
     let len = bytes.len() - 1;
 
     bytes.iter().enumerate().fold(0, |s, (i, &b)| {
-        s | (b & 1) << (len - i)
+        s | ((b & 1) as u16) << (len - i)
     })
 }
 
+/// Unpack the given symbol into one bit per byte, MSB first. This is the inverse of
+/// `bytes_to_bits`.
+pub fn bits_to_bytes(symbol: u16, bytes: &mut [u8]) {
+    let len = bytes.len() - 1;
+
+    for (i, b) in bytes.iter_mut().enumerate() {
+        *b = (symbol >> (len - i) & 1) as u8;
+    }
+}
+
 pub fn max_symbol(bits_per_symbol: usize) -> usize {
     (1 << bits_per_symbol) - 1
 }
 
 #[cfg(test)]
 mod test {
-    use super::{bytes_to_bits, max_symbol};
+    use super::{bytes_to_bits, bits_to_bytes, max_symbol};
 
     #[test]
     fn test_b2b() {
@@ -24,6 +35,26 @@ mod test {
         assert_eq!(bytes_to_bits(&[0, 1, 0, 1]), 0b0101);
     }
 
+    #[test]
+    fn test_bits_to_bytes() {
+        let mut b = [0; 4];
+
+        bits_to_bytes(0b0001, &mut b);
+        assert_eq!(b, [0, 0, 0, 1]);
+
+        bits_to_bytes(0b0101, &mut b);
+        assert_eq!(b, [0, 1, 0, 1]);
+
+        bits_to_bytes(bytes_to_bits(&[1, 1, 0, 1]), &mut b);
+        assert_eq!(b, [1, 1, 0, 1]);
+
+        // Symbols wider than a byte.
+        let mut b = [0; 12];
+        bits_to_bytes(0b101100111001, &mut b);
+        assert_eq!(b, [1, 0, 1, 1, 0, 0, 1, 1, 1, 0, 0, 1]);
+        assert_eq!(bytes_to_bits(&b), 0b101100111001);
+    }
+
     #[test]
     fn test_max_symbol() {
         assert_eq!(max_symbol(1), 0b1);
@@ -0,0 +1,414 @@
+/// Implements the fast Fourier transform over complex and real samples.
+///
+/// Power-of-two sizes use an in-place radix-2 transform, and other sizes use a recursive
+/// mixed-radix transform over the prime factors of the size. Each `FFT` precomputes its
+/// twiddle factors, and a `Planner` caches transforms by size so they can be shared.
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+use num::complex::Complex32;
+
+/// Compute the forward DFT of the given buffer in place. This plans a new transform on
+/// every call, so repeated transforms should use an `FFT` or `Planner` instead.
+pub fn fft(buf: &mut [Complex32]) {
+    FFT::new(buf.len()).forward(buf);
+}
+
+/// Compute the inverse DFT of the given buffer in place, scaled by 1/N so it inverts
+/// `fft`.
+pub fn ifft(buf: &mut [Complex32]) {
+    FFT::new(buf.len()).inverse(buf);
+}
+
+/// Complex FFT of a fixed size.
+pub struct FFT {
+    /// Twiddle factors e^(-2πik/N) for k in 0..N.
+    twiddles: Vec<Complex32>,
+    /// Prime factors of the size, for the mixed-radix transform.
+    factors: Vec<usize>,
+}
+
+impl FFT {
+    /// Plan a transform of the given size.
+    pub fn new(size: usize) -> FFT {
+        assert!(size > 0);
+
+        FFT {
+            twiddles: (0..size).map(|k| {
+                Complex32::from_polar(&1.0, &(-2.0 * PI * k as f32 / size as f32))
+            }).collect(),
+            factors: factor(size),
+        }
+    }
+
+    /// Get the transform size.
+    pub fn len(&self) -> usize {
+        self.twiddles.len()
+    }
+
+    /// Check if the transform size is zero.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Compute the forward DFT of the given buffer in place.
+    pub fn forward(&self, buf: &mut [Complex32]) {
+        assert!(buf.len() == self.len());
+
+        if self.len().is_power_of_two() {
+            self.radix2(buf);
+        } else {
+            let input = buf.to_vec();
+
+            // Butterfly inputs, sized for the largest radix.
+            let mut tw = vec![Complex32::new(0.0, 0.0); *self.factors.last().unwrap()];
+
+            self.mixed(&input[..], 1, buf, &self.factors[..], &mut tw[..]);
+        }
+    }
+
+    /// Compute the inverse DFT of the given buffer in place, scaled by 1/N.
+    pub fn inverse(&self, buf: &mut [Complex32]) {
+        // The inverse is the conjugate of the forward transform of the conjugate.
+        for x in buf.iter_mut() {
+            *x = x.conj();
+        }
+
+        self.forward(buf);
+
+        let scale = 1.0 / buf.len() as f32;
+
+        for x in buf.iter_mut() {
+            *x = x.conj() * scale;
+        }
+    }
+
+    /// Iterative decimation-in-time transform for power-of-two sizes.
+    fn radix2(&self, buf: &mut [Complex32]) {
+        let n = buf.len();
+        let bits = n.trailing_zeros();
+
+        // Put the samples in bit-reversed order.
+        for i in 0..n {
+            let j = reverse(i, bits);
+
+            if i < j {
+                buf.swap(i, j);
+            }
+        }
+
+        let mut half = 1;
+
+        while half < n {
+            let step = n / (2 * half);
+
+            for k in 0..half {
+                let w = self.twiddles[k * step];
+                let mut start = 0;
+
+                while start < n {
+                    let a = buf[start + k];
+                    let b = buf[start + k + half] * w;
+
+                    buf[start + k] = a + b;
+                    buf[start + k + half] = a - b;
+
+                    start += 2 * half;
+                }
+            }
+
+            half *= 2;
+        }
+    }
+
+    /// Recursive decimation-in-time transform of every `stride`th input into the given
+    /// output, splitting by the first of the given factors.
+    fn mixed(&self, input: &[Complex32], stride: usize, output: &mut [Complex32],
+             factors: &[usize], tw: &mut [Complex32])
+    {
+        let n = output.len();
+        let p = factors[0];
+        let m = n / p;
+
+        // Transform each of the p interleaved subsequences into consecutive blocks.
+        if m > 1 {
+            for q in 0..p {
+                self.mixed(&input[q * stride..], stride * p,
+                           &mut output[q * m..(q + 1) * m], &factors[1..], tw);
+            }
+        } else {
+            for q in 0..p {
+                output[q] = input[q * stride];
+            }
+        }
+
+        // Combine the blocks with radix-p butterflies.
+        let step = self.len() / n;
+        let root = self.len() / p;
+
+        for k in 0..m {
+            for q in 0..p {
+                tw[q] = output[q * m + k] * self.twiddles[q * k * step];
+            }
+
+            for r in 0..p {
+                output[r * m + k] = (0..p).fold(Complex32::new(0.0, 0.0), |s, q| {
+                    s + tw[q] * self.twiddles[(q * r % p) * root]
+                });
+            }
+        }
+    }
+}
+
+/// Forward and inverse FFT of real samples with an even size, computed by a complex FFT
+/// of half the size.
+pub struct RealFFT {
+    fft: FFT,
+    /// Twiddle factors e^(-2πik/N) for k in 0..N/2.
+    twiddles: Vec<Complex32>,
+}
+
+impl RealFFT {
+    /// Plan a transform of the given (even) size.
+    pub fn new(size: usize) -> RealFFT {
+        assert!(size > 0 && size % 2 == 0);
+
+        RealFFT {
+            fft: FFT::new(size / 2),
+            twiddles: (0..size / 2).map(|k| {
+                Complex32::from_polar(&1.0, &(-2.0 * PI * k as f32 / size as f32))
+            }).collect(),
+        }
+    }
+
+    /// Get the transform size.
+    pub fn len(&self) -> usize {
+        2 * self.fft.len()
+    }
+
+    /// Check if the transform size is zero.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Compute the DFT of the given real samples into the given output, which holds the
+    /// N/2 + 1 nonnegative frequency bins (the rest are their conjugates.)
+    pub fn forward(&self, input: &[f32], output: &mut [Complex32]) {
+        let half = self.fft.len();
+
+        assert!(input.len() == self.len());
+        assert!(output.len() == half + 1);
+
+        // Pack even samples into the real part and odd into the imaginary.
+        let mut z: Vec<Complex32> = (0..half).map(|n| {
+            Complex32::new(input[2 * n], input[2 * n + 1])
+        }).collect();
+
+        self.fft.forward(&mut z[..]);
+
+        // Separate the transforms of the even and odd samples and combine them.
+        for k in 0..half + 1 {
+            let a = z[k % half];
+            let b = z[(half - k) % half].conj();
+
+            let even = (a + b) * 0.5;
+            let odd = (a - b) * Complex32::new(0.0, -0.5);
+
+            output[k] = even + odd * self.twiddle(k);
+        }
+    }
+
+    /// Compute the real samples from the given N/2 + 1 nonnegative frequency bins into
+    /// the given output, scaled by 1/N so it inverts `forward`.
+    pub fn inverse(&self, input: &[Complex32], output: &mut [f32]) {
+        let half = self.fft.len();
+
+        assert!(input.len() == half + 1);
+        assert!(output.len() == self.len());
+
+        // Undo the separation into even and odd transforms.
+        let mut z: Vec<Complex32> = (0..half).map(|k| {
+            let a = input[k];
+            let b = input[half - k].conj();
+
+            let even = (a + b) * 0.5;
+            let odd = (a - b) * 0.5 * self.twiddle(k).conj();
+
+            even + odd * Complex32::new(0.0, 1.0)
+        }).collect();
+
+        self.fft.inverse(&mut z[..]);
+
+        for n in 0..half {
+            output[2 * n] = z[n].re;
+            output[2 * n + 1] = z[n].im;
+        }
+    }
+
+    /// Get e^(-2πik/N) for k in 0..N/2+1.
+    fn twiddle(&self, k: usize) -> Complex32 {
+        if k < self.twiddles.len() {
+            self.twiddles[k]
+        } else {
+            Complex32::new(-1.0, 0.0)
+        }
+    }
+}
+
+/// Caches transforms by size, so the twiddle factors are only computed once.
+pub struct Planner {
+    complex: HashMap<usize, Rc<FFT>>,
+    real: HashMap<usize, Rc<RealFFT>>,
+}
+
+impl Planner {
+    pub fn new() -> Planner {
+        Planner {
+            complex: HashMap::new(),
+            real: HashMap::new(),
+        }
+    }
+
+    /// Get a complex transform of the given size.
+    pub fn plan(&mut self, size: usize) -> Rc<FFT> {
+        self.complex.entry(size).or_insert_with(|| Rc::new(FFT::new(size))).clone()
+    }
+
+    /// Get a real transform of the given (even) size.
+    pub fn plan_real(&mut self, size: usize) -> Rc<RealFFT> {
+        self.real.entry(size).or_insert_with(|| Rc::new(RealFFT::new(size))).clone()
+    }
+}
+
+impl Default for Planner {
+    fn default() -> Planner {
+        Planner::new()
+    }
+}
+
+/// Reverse the lower given number of bits.
+fn reverse(x: usize, bits: u32) -> usize {
+    (0..bits).fold(0, |r, b| (r << 1) | ((x >> b) & 1))
+}
+
+/// Compute the prime factors of the given number in increasing order, or just 1 for 1.
+fn factor(mut n: usize) -> Vec<usize> {
+    if n == 1 {
+        return vec![1];
+    }
+
+    let mut factors = vec![];
+    let mut p = 2;
+
+    while n > 1 {
+        if p * p > n {
+            factors.push(n);
+            break;
+        }
+
+        if n % p == 0 {
+            factors.push(p);
+            n /= p;
+        } else {
+            p += 1;
+        }
+    }
+
+    factors
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+
+    use num::complex::Complex32;
+    use super::{fft, ifft, factor, FFT, RealFFT, Planner};
+
+    fn dft(x: &[Complex32]) -> Vec<Complex32> {
+        let n = x.len();
+
+        (0..n).map(|k| {
+            x.iter().enumerate().fold(Complex32::new(0.0, 0.0), |s, (t, &x)| {
+                s + x * Complex32::from_polar(&1.0, &(-2.0 * PI * (k * t % n) as f32 /
+                                                      n as f32))
+            })
+        }).collect()
+    }
+
+    fn signal(n: usize) -> Vec<Complex32> {
+        (0..n).map(|i| {
+            Complex32::new((i as f32 * 0.37).sin(), (i as f32 * 1.3).cos())
+        }).collect()
+    }
+
+    #[test]
+    fn test_factor() {
+        assert_eq!(factor(1), vec![1]);
+        assert_eq!(factor(7), vec![7]);
+        assert_eq!(factor(12), vec![2, 2, 3]);
+        assert_eq!(factor(360), vec![2, 2, 2, 3, 3, 5]);
+    }
+
+    #[test]
+    fn test_fft() {
+        // Power-of-two and mixed-radix sizes, including primes.
+        for &n in &[1, 2, 3, 8, 12, 15, 45, 64, 97, 100] {
+            let x = signal(n);
+
+            let mut y = x.clone();
+            fft(&mut y[..]);
+
+            for (a, b) in y.iter().zip(dft(&x[..]).iter()) {
+                assert!((*a - *b).norm() < 1e-4);
+            }
+
+            ifft(&mut y[..]);
+
+            for (a, b) in y.iter().zip(x.iter()) {
+                assert!((*a - *b).norm() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_real() {
+        for &n in &[2, 8, 30, 64] {
+            let x: Vec<f32> = (0..n).map(|i| (i as f32 * 0.7).sin() + 0.25).collect();
+            let f = RealFFT::new(n);
+
+            let mut y = vec![Complex32::new(0.0, 0.0); n / 2 + 1];
+            f.forward(&x[..], &mut y[..]);
+
+            let complex: Vec<Complex32> = x.iter().map(|&x| Complex32::new(x, 0.0))
+                                           .collect();
+
+            for (a, b) in y.iter().zip(dft(&complex[..]).iter()) {
+                assert!((*a - *b).norm() < 1e-4);
+            }
+
+            let mut z = vec![0.0; n];
+            f.inverse(&y[..], &mut z[..]);
+
+            for (a, b) in z.iter().zip(x.iter()) {
+                assert!((a - b).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_planner() {
+        let mut p = Planner::new();
+
+        let a = p.plan(48);
+        assert_eq!(&*a as *const FFT, &*p.plan(48) as *const FFT);
+        assert_eq!(a.len(), 48);
+        assert_eq!(p.plan(64).len(), 64);
+
+        let r = p.plan_real(48);
+        assert_eq!(&*r as *const RealFFT, &*p.plan_real(48) as *const RealFFT);
+        assert_eq!(r.len(), 48);
+        assert!(!r.is_empty());
+    }
+}
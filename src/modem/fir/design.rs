@@ -0,0 +1,272 @@
+/// Implements FIR filter design by the window method: the ideal (infinite) impulse
+/// response for each filter type is truncated to the requested number of taps and
+/// shaped by a window function, which trades transition width for stopband attenuation.
+
+use std::f32::consts::PI;
+
+use freq::Freq;
+
+/// Window function applied to the ideal impulse response.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Window {
+    /// No shaping: narrowest transition but only ~21dB attenuation.
+    Rectangular,
+    /// ~53dB attenuation.
+    Hamming,
+    /// ~74dB attenuation with a wider transition than Hamming.
+    Blackman,
+    /// Kaiser window with the given beta, which sets the attenuation.
+    Kaiser(f32),
+}
+
+impl Window {
+    /// Compute the window coefficient for the given tap out of the given total taps.
+    pub fn coef(&self, n: usize, taps: usize) -> f32 {
+        if taps == 1 {
+            return 1.0;
+        }
+
+        let x = n as f32 / (taps - 1) as f32;
+
+        match *self {
+            Window::Rectangular => 1.0,
+            Window::Hamming => 0.54 - 0.46 * (2.0 * PI * x).cos(),
+            Window::Blackman =>
+                0.42 - 0.5 * (2.0 * PI * x).cos() + 0.08 * (4.0 * PI * x).cos(),
+            Window::Kaiser(beta) => {
+                let r = 2.0 * x - 1.0;
+                bessel_i0(beta * (1.0 - r * r).sqrt()) / bessel_i0(beta)
+            },
+        }
+    }
+}
+
+/// Compute the zeroth-order modified Bessel function of the first kind by its power
+/// series.
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+
+    loop {
+        term *= (x / (2.0 * k)) * (x / (2.0 * k));
+        sum += term;
+
+        if term < sum * 1e-9 {
+            return sum;
+        }
+
+        k += 1.0;
+    }
+}
+
+/// Estimate the Kaiser window needed to meet the given passband ripple (as a linear
+/// deviation from unity) and stopband attenuation (dB) with the given transition width.
+/// Returns the (odd) number of taps and the window.
+pub fn kaiser(ripple: f32, attenuation: f32, transition: Freq) -> (usize, Window) {
+    // The window method gives equal ripple in both bands, so the tighter spec wins.
+    let atten = attenuation.max(-20.0 * ripple.log10());
+
+    let beta = if atten > 50.0 {
+        0.1102 * (atten - 8.7)
+    } else if atten >= 21.0 {
+        0.5842 * (atten - 21.0).powf(0.4) + 0.07886 * (atten - 21.0)
+    } else {
+        0.0
+    };
+
+    let order = ((atten - 7.95) / (2.285 * transition.sample_freq())).ceil() as usize;
+
+    ((order + 1) | 1, Window::Kaiser(beta))
+}
+
+/// Compute the magnitude response of the given taps at the given frequency
+/// (radians/sample.)
+fn gain(coefs: &[f32], w: f32) -> f32 {
+    let (re, im) = coefs.iter().enumerate().fold((0.0, 0.0), |(re, im), (n, &c)| {
+        (re + c * (w * n as f32).cos(), im - c * (w * n as f32).sin())
+    });
+
+    (re * re + im * im).sqrt()
+}
+
+/// Compute the windowed ideal impulse response given by `ideal`, which takes the tap
+/// offset from the center, and normalize it to unity gain at the given frequency.
+fn windowed<F>(taps: usize, window: Window, norm: f32, ideal: F) -> Vec<f32>
+    where F: Fn(f32) -> f32
+{
+    assert!(taps % 2 == 1);
+
+    let center = (taps / 2) as f32;
+
+    let coefs: Vec<f32> = (0..taps).map(|n| {
+        ideal(n as f32 - center) * window.coef(n, taps)
+    }).collect();
+
+    let g = gain(&coefs[..], norm);
+    coefs.iter().map(|c| c / g).collect()
+}
+
+/// Ideal lowpass impulse response with the given cutoff (radians/sample) at the given
+/// tap offset.
+fn sinc(wc: f32, t: f32) -> f32 {
+    if t == 0.0 { wc / PI } else { (wc * t).sin() / (PI * t) }
+}
+
+/// Design a lowpass filter with the given cutoff frequency and (odd) number of taps.
+/// The filter has unity gain at DC.
+pub fn lowpass(cutoff: Freq, taps: usize, window: Window) -> Vec<f32> {
+    let wc = cutoff.sample_freq();
+    windowed(taps, window, 0.0, |t| sinc(wc, t))
+}
+
+/// Design a highpass filter with the given cutoff frequency and (odd) number of taps.
+/// The filter has unity gain at the Nyquist frequency.
+pub fn highpass(cutoff: Freq, taps: usize, window: Window) -> Vec<f32> {
+    let wc = cutoff.sample_freq();
+    windowed(taps, window, PI, |t| sinc(PI, t) - sinc(wc, t))
+}
+
+/// Design a bandpass filter passing between the given frequencies with the given (odd)
+/// number of taps. The filter has unity gain at the center of the passband.
+pub fn bandpass(low: Freq, high: Freq, taps: usize, window: Window) -> Vec<f32> {
+    let (wl, wh) = (low.sample_freq(), high.sample_freq());
+    assert!(wl < wh);
+
+    windowed(taps, window, (wl + wh) / 2.0, |t| sinc(wh, t) - sinc(wl, t))
+}
+
+/// Design a bandstop filter rejecting between the given frequencies with the given (odd)
+/// number of taps. The filter has unity gain at DC.
+pub fn bandstop(low: Freq, high: Freq, taps: usize, window: Window) -> Vec<f32> {
+    let (wl, wh) = (low.sample_freq(), high.sample_freq());
+    assert!(wl < wh);
+
+    windowed(taps, window, 0.0, |t| sinc(PI, t) - sinc(wh, t) + sinc(wl, t))
+}
+
+/// Design a Hilbert transform filter with the given (odd) number of taps. The output is
+/// delayed by half the taps.
+pub fn hilbert(taps: usize, window: Window) -> Vec<f32> {
+    assert!(taps % 2 == 1);
+
+    let center = taps / 2;
+
+    (0..taps).map(|n| {
+        if (n + center) % 2 == 0 {
+            0.0
+        } else {
+            2.0 / (PI * (n as f32 - center as f32)) * window.coef(n, taps)
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+
+    use freq::Freq;
+    use fir::FIRFilter;
+    use super::{Window, kaiser, gain, lowpass, highpass, bandpass, bandstop, hilbert};
+
+    // Convert Hz to radians/sample at 10kHz.
+    fn rads(hz: f32) -> f32 {
+        2.0 * PI * hz / 10000.0
+    }
+
+    fn db(g: f32) -> f32 {
+        20.0 * g.log10()
+    }
+
+    #[test]
+    fn test_windows() {
+        for &w in &[Window::Rectangular, Window::Hamming, Window::Blackman,
+                    Window::Kaiser(5.0)] {
+            // Windows are symmetric and peak at the center.
+            assert!((w.coef(0, 21) - w.coef(20, 21)).abs() < 1e-6);
+            assert!((w.coef(3, 21) - w.coef(17, 21)).abs() < 1e-6);
+            assert!((w.coef(10, 21) - 1.0).abs() < 1e-6);
+        }
+
+        assert!((Window::Hamming.coef(0, 21) - 0.08).abs() < 1e-6);
+        assert!(Window::Blackman.coef(0, 21).abs() < 1e-6);
+        assert!((Window::Kaiser(0.0).coef(0, 21) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_kaiser() {
+        // 60dB over a transition of 0.05 cycles/sample.
+        let (taps, w) = kaiser(0.01, 60.0, Freq::new(500, 10000));
+        assert_eq!(taps, 75);
+        assert_eq!(w, Window::Kaiser(0.1102 * (60.0 - 8.7)));
+
+        // The ripple spec dominates here.
+        let (_, w) = kaiser(0.0001, 40.0, Freq::new(500, 10000));
+        assert_eq!(w, Window::Kaiser(0.1102 * (80.0 - 8.7)));
+
+        // A lowpass designed to the spec meets it.
+        let (taps, w) = kaiser(0.01, 60.0, Freq::new(500, 10000));
+        let coefs = lowpass(Freq::new(1250, 10000), taps, w);
+
+        for hz in (0..10).map(|x| x as f32 * 100.0) {
+            assert!((gain(&coefs[..], rads(hz)) - 1.0).abs() < 0.01);
+        }
+
+        for hz in (0..35).map(|x| 1500.0 + x as f32 * 100.0) {
+            assert!(db(gain(&coefs[..], rads(hz))) < -60.0);
+        }
+    }
+
+    #[test]
+    fn test_lowpass() {
+        let coefs = lowpass(Freq::new(1000, 10000), 41, Window::Hamming);
+
+        assert!((gain(&coefs[..], 0.0) - 1.0).abs() < 1e-5);
+        assert!((gain(&coefs[..], rads(1000.0)) - 0.5).abs() < 0.02);
+        assert!(db(gain(&coefs[..], rads(2000.0))) < -50.0);
+    }
+
+    #[test]
+    fn test_highpass() {
+        let coefs = highpass(Freq::new(2000, 10000), 41, Window::Blackman);
+
+        assert!((gain(&coefs[..], PI) - 1.0).abs() < 1e-5);
+        assert!((gain(&coefs[..], rads(2000.0)) - 0.5).abs() < 0.02);
+        assert!(db(gain(&coefs[..], rads(500.0))) < -70.0);
+    }
+
+    #[test]
+    fn test_bandpass() {
+        let coefs = bandpass(Freq::new(1000, 10000), Freq::new(3000, 10000), 61,
+                             Window::Hamming);
+
+        assert!((gain(&coefs[..], rads(2000.0)) - 1.0).abs() < 1e-5);
+        assert!(db(gain(&coefs[..], rads(200.0))) < -50.0);
+        assert!(db(gain(&coefs[..], rads(4000.0))) < -50.0);
+    }
+
+    #[test]
+    fn test_bandstop() {
+        let coefs = bandstop(Freq::new(1000, 10000), Freq::new(3000, 10000), 61,
+                             Window::Hamming);
+
+        assert!((gain(&coefs[..], 0.0) - 1.0).abs() < 1e-5);
+        assert!((gain(&coefs[..], PI) - 1.0).abs() < 0.01);
+        assert!(db(gain(&coefs[..], rads(2000.0))) < -50.0);
+    }
+
+    #[test]
+    fn test_hilbert() {
+        let taps = hilbert(23, Window::Hamming);
+        let mut f = FIRFilter::new(&taps[..]);
+
+        // Shifts a cosine to a sine, delayed by half the taps.
+        for s in 0..200 {
+            let y = f.add((s as f32 * 0.5).cos());
+
+            if s > 50 {
+                assert!((y - ((s - 11) as f32 * 0.5).sin()).abs() < 0.05);
+            }
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use std;
+use std::borrow::Cow;
+use std::ops::Add;
+
+use num::Zero;
+use num::complex::Complex32;
+
+use fft;
+use freq::Freq;
+
+pub mod design;
+pub mod remez;
+
+/// Minimum number of taps for block processing to use FFT convolution.
+const FFT_TAPS: usize = 64;
+
+/// A sample type that can be filtered.
+pub trait Sample: Copy + Zero + Add<Output = Self> {
+    /// Convert the sample to a complex number.
+    fn complex(&self) -> Complex32;
+
+    /// Convert a complex number to a sample, discarding the imaginary part of real
+    /// samples.
+    fn from_complex(x: Complex32) -> Self;
+}
+
+impl Sample for f32 {
+    fn complex(&self) -> Complex32 { Complex32::new(*self, 0.0) }
+    fn from_complex(x: Complex32) -> f32 { x.re }
+}
+
+impl Sample for Complex32 {
+    fn complex(&self) -> Complex32 { *self }
+    fn from_complex(x: Complex32) -> Complex32 { x }
+}
+
+/// A filter tap that can be applied to samples of type `T`.
+pub trait Coef<T>: Copy {
+    /// Scale the given sample by the tap.
+    fn apply(&self, sample: T) -> T;
+
+    /// Get the tap as a complex number.
+    fn complex(&self) -> Complex32;
+}
+
+impl Coef<f32> for f32 {
+    fn apply(&self, sample: f32) -> f32 { sample * *self }
+    fn complex(&self) -> Complex32 { Complex32::new(*self, 0.0) }
+}
+
+impl Coef<Complex32> for f32 {
+    fn apply(&self, sample: Complex32) -> Complex32 {
+        Complex32::new(sample.re * *self, sample.im * *self)
+    }
+
+    fn complex(&self) -> Complex32 { Complex32::new(*self, 0.0) }
+}
+
+impl Coef<Complex32> for Complex32 {
+    fn apply(&self, sample: Complex32) -> Complex32 { sample * *self }
+    fn complex(&self) -> Complex32 { *self }
+}
+
+/// FIR filter over samples of type `T` with taps of type `C`, which can either be
+/// borrowed (such as a static table) or owned (such as from `design`.)
+pub struct FIRFilter<'a, T = f32, C = f32> where C: 'a + Clone {
+    coefs: Cow<'a, [C]>,
+    history: Vec<T>,
+    idx: usize,
+    /// Transformed taps for block processing long filters.
+    fast: Option<OverlapSave>,
+    /// History followed by the input block, kept between calls to `process` so each
+    /// block doesn't allocate.
+    ext: Vec<T>,
+}
+
+impl<'a, T, C> FIRFilter<'a, T, C> where T: Sample, C: Coef<T> {
+    pub fn new<K>(coefs: K) -> FIRFilter<'a, T, C>
+        where K: Into<Cow<'a, [C]>>
+    {
+        let coefs = coefs.into();
+        let len = coefs.len();
+
+        let fast = if len >= FFT_TAPS {
+            Some(OverlapSave::new::<T, C>(&coefs[..]))
+        } else {
+            None
+        };
+
+        FIRFilter {
+            coefs: coefs,
+            history: vec![T::zero(); len],
+            idx: 0,
+            fast: fast,
+            ext: vec![],
+        }
+    }
+
+    /// Get the filter taps.
+    pub fn coefs(&self) -> &[C] {
+        &self.coefs[..]
+    }
+
+    fn calc(&self) -> T {
+        let mut cur = self.idx;
+
+        self.coefs.iter().fold(T::zero(), |s, coef| {
+            cur = if cur == 0 { self.history.len() - 1 } else { cur - 1 };
+            s + coef.apply(self.history[cur])
+        })
+    }
+
+    pub fn add(&mut self, sample: T) -> T {
+        self.history[self.idx] = sample;
+
+        self.idx += 1;
+        self.idx %= self.history.len();
+
+        self.calc()
+    }
+
+    /// Filter the given block of samples into the given output, which must be the same
+    /// length. This continues from the same history as `add`, but avoids the per-sample
+    /// overhead, and long filters are computed by FFT convolution.
+    pub fn process(&mut self, input: &[T], output: &mut [T]) {
+        assert!(input.len() == output.len());
+
+        if input.is_empty() {
+            return;
+        }
+
+        let len = self.coefs.len();
+
+        // Line up the history, oldest first, ahead of the input.
+        let ext = &mut self.ext;
+        ext.clear();
+
+        for i in 1..len {
+            ext.push(self.history[(self.idx + i) % len]);
+        }
+
+        ext.extend_from_slice(input);
+
+        match self.fast {
+            Some(ref mut os) if input.len() >= len => os.convolve(&ext[..], output),
+            _ => for (n, y) in output.iter_mut().enumerate() {
+                // Output n lines up with ext[n + len - 1].
+                *y = self.coefs.iter().enumerate().fold(T::zero(), |s, (k, c)| {
+                    s + c.apply(ext[n + len - 1 - k])
+                });
+            },
+        }
+
+        // Keep the newest samples as history.
+        let start = ext.len() - len;
+        self.history.copy_from_slice(&ext[start..]);
+        self.idx = 0;
+    }
+
+    /// Clear the filter history, as if it was just created.
+    pub fn reset(&mut self) {
+        for x in self.history.iter_mut() {
+            *x = T::zero();
+        }
+
+        self.idx = 0;
+    }
+
+    /// Compute the complex frequency response at the given frequency.
+    pub fn response(&self, f: Freq) -> Complex32 {
+        let w = f.sample_freq();
+
+        self.coefs.iter().enumerate().fold(Complex32::new(0.0, 0.0), |s, (n, c)| {
+            s + c.complex() * Complex32::from_polar(&1.0, &(-w * n as f32))
+        })
+    }
+
+    /// Compute the group delay (samples) at the given frequency. For linear-phase
+    /// (symmetric) taps, this is half the filter length at every frequency.
+    pub fn group_delay(&self, f: Freq) -> f32 {
+        let w = f.sample_freq();
+
+        // The group delay is Re{DFT[n·h(n)] / DFT[h(n)]}.
+        let ramp = self.coefs.iter().enumerate().fold(Complex32::new(0.0, 0.0),
+            |s, (n, c)| {
+                s + c.complex() * Complex32::from_polar(&(n as f32), &(-w * n as f32))
+            });
+
+        (ramp / self.response(f)).re
+    }
+}
+
+/// Overlap-save FFT convolution.
+struct OverlapSave {
+    fft: fft::FFT,
+    /// Transform of the zero-padded taps.
+    taps: Vec<Complex32>,
+    /// Number of taps.
+    len: usize,
+    /// Block being transformed, kept between calls.
+    buf: Vec<Complex32>,
+}
+
+impl OverlapSave {
+    fn new<T, C: Coef<T>>(coefs: &[C]) -> OverlapSave {
+        // A transform of around 4x the taps balances the per-block cost against the
+        // overlap wasted on each block.
+        let size = (4 * coefs.len()).next_power_of_two();
+
+        let mut taps: Vec<Complex32> = coefs.iter().map(|c| c.complex()).collect();
+        taps.resize(size, Complex32::new(0.0, 0.0));
+
+        let fft = fft::FFT::new(size);
+        fft.forward(&mut taps[..]);
+
+        OverlapSave {
+            fft: fft,
+            taps: taps,
+            len: coefs.len(),
+            buf: vec![Complex32::new(0.0, 0.0); size],
+        }
+    }
+
+    /// Convolve the given samples, where the first (taps - 1) are history, into the
+    /// given output.
+    fn convolve<T: Sample>(&mut self, ext: &[T], output: &mut [T]) {
+        let size = self.taps.len();
+        let overlap = self.len - 1;
+        let step = size - overlap;
+
+        let buf = &mut self.buf;
+        let mut start = 0;
+
+        while start < output.len() {
+            let avail = std::cmp::min(size, ext.len() - start);
+
+            for i in 0..size {
+                buf[i] = if i < avail { ext[start + i].complex() }
+                         else { Complex32::new(0.0, 0.0) };
+            }
+
+            self.fft.forward(&mut buf[..]);
+
+            for (x, h) in buf.iter_mut().zip(self.taps.iter()) {
+                *x = *x * *h;
+            }
+
+            self.fft.inverse(&mut buf[..]);
+
+            // The first outputs are corrupted by wraparound, so only the rest are kept.
+            let count = std::cmp::min(step, output.len() - start);
+
+            for i in 0..count {
+                output[start + i] = T::from_complex(buf[overlap + i]);
+            }
+
+            start += step;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use num::complex::Complex32;
+
+    use freq::Freq;
+    use super::FIRFilter;
+
+    #[test]
+    fn test_fir() {
+        const COEFS: &'static [f32] = &[1.0, 2.0, 3.0];
+        let mut f = FIRFilter::new(COEFS);
+
+        assert_eq!(f.add(1.0), 1.0);
+        assert_eq!(f.add(0.0), 2.0);
+        assert_eq!(f.add(0.0), 3.0);
+        assert_eq!(f.add(1.0), 1.0);
+        assert_eq!(f.add(1.0), 3.0);
+        assert_eq!(f.add(1.0), 6.0);
+        assert_eq!(f.add(1.0), 6.0);
+
+        // Owned taps work the same, and reset clears the history.
+        let mut f = FIRFilter::new(COEFS.to_vec());
+
+        assert_eq!(f.add(1.0), 1.0);
+        assert_eq!(f.add(1.0), 3.0);
+
+        f.reset();
+
+        assert_eq!(f.add(1.0), 1.0);
+        assert_eq!(f.add(0.0), 2.0);
+    }
+
+    #[test]
+    fn test_complex() {
+        // Complex samples with real taps.
+        let mut f: FIRFilter<Complex32> = FIRFilter::new(vec![1.0, 2.0]);
+
+        assert_eq!(f.add(Complex32::new(1.0, -1.0)), Complex32::new(1.0, -1.0));
+        assert_eq!(f.add(Complex32::new(0.0, 0.0)), Complex32::new(2.0, -2.0));
+
+        // Complex samples with complex taps.
+        let mut f = FIRFilter::new(vec![Complex32::new(0.0, 1.0), Complex32::new(2.0, 0.0)]);
+
+        assert_eq!(f.add(Complex32::new(1.0, 1.0)), Complex32::new(-1.0, 1.0));
+        assert_eq!(f.add(Complex32::new(0.0, 0.0)), Complex32::new(2.0, 2.0));
+    }
+
+    #[test]
+    fn test_response() {
+        let f = FIRFilter::<f32>::new(vec![0.25, 0.5, 0.25]);
+
+        // Lowpass with unity gain at DC and a zero at Nyquist.
+        assert!((f.response(Freq::new(0, 1000)).norm() - 1.0).abs() < 1e-6);
+        assert!(f.response(Freq::new(500, 1000)).norm() < 1e-6);
+        assert!((f.response(Freq::new(250, 1000)).norm() - 0.5).abs() < 1e-6);
+
+        // Symmetric taps have constant group delay.
+        assert!((f.group_delay(Freq::new(0, 1000)) - 1.0).abs() < 1e-5);
+        assert!((f.group_delay(Freq::new(100, 1000)) - 1.0).abs() < 1e-5);
+
+        let f = FIRFilter::<f32>::new(vec![0.0, 0.0, 1.0, 0.5]);
+        assert!((f.group_delay(Freq::new(0, 1000)) - 7.0 / 3.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_process() {
+        let input: Vec<f32> = (0..1000).map(|i| (i as f32 * 0.1).sin() + (i % 7) as f32)
+                                       .collect();
+
+        // Short taps are convolved directly and long taps by FFT.
+        for &taps in &[5, 200] {
+            let coefs: Vec<f32> = (0..taps).map(|i| (i as f32 * 0.3).cos() / taps as f32)
+                                           .collect();
+
+            let mut a = FIRFilter::new(&coefs[..]);
+            let expected: Vec<f32> = input.iter().map(|&x| a.add(x)).collect();
+
+            // Blocks continue from each other and from single samples, and may be
+            // empty.
+            let mut b = FIRFilter::new(&coefs[..]);
+            let mut out = vec![0.0; 1000];
+
+            b.process(&input[..300], &mut out[..300]);
+            out[300] = b.add(input[300]);
+            b.process(&input[301..301], &mut out[301..301]);
+            b.process(&input[301..310], &mut out[301..310]);
+            b.process(&input[310..], &mut out[310..]);
+
+            for (x, e) in out.iter().zip(expected.iter()) {
+                assert!((x - e).abs() < 1e-4);
+            }
+        }
+
+        // Complex samples by FFT.
+        let coefs: Vec<f32> = (0..100).map(|i| 1.0 / (i + 1) as f32).collect();
+        let input: Vec<Complex32> = (0..500).map(|i| {
+            Complex32::from_polar(&1.0, &(i as f32 * 0.2))
+        }).collect();
+
+        let mut a = FIRFilter::new(&coefs[..]);
+        let mut b = FIRFilter::new(&coefs[..]);
+        let mut out = vec![Complex32::new(0.0, 0.0); 500];
+
+        b.process(&input[..], &mut out[..]);
+
+        for (&x, y) in input.iter().zip(out.iter()) {
+            assert!((a.add(x) - *y).norm() < 1e-4);
+        }
+    }
+}
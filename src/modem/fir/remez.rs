@@ -0,0 +1,378 @@
+/// Implements the Parks-McClellan algorithm, which designs linear-phase FIR filters that
+/// are optimal in the sense of minimizing the maximum weighted error over a set of
+/// bands. The resulting error ripples with equal amplitude across each band, so for a
+/// given spec it needs fewer taps than a windowed design.
+///
+/// The filter's amplitude response is found by the Remez exchange algorithm over a dense
+/// grid of frequencies in the bands, then sampled and inverse transformed to give the
+/// taps.
+
+use std;
+use std::f64::consts::PI;
+
+use freq::Freq;
+
+/// Number of grid points per coefficient in each band.
+const GRID_DENSITY: usize = 16;
+
+/// Maximum number of exchange iterations.
+const MAX_ITERATIONS: usize = 40;
+
+/// Band specification for the designer.
+pub struct Band {
+    /// Lower edge (cycles/sample.)
+    low: f64,
+    /// Upper edge (cycles/sample.)
+    high: f64,
+    /// Desired gain over the band.
+    gain: f64,
+    /// Relative weight of errors in the band.
+    weight: f64,
+}
+
+impl Band {
+    /// Create a new band between the given edges with the given desired gain and error
+    /// weight. Bands with a higher weight get a proportionally smaller ripple.
+    pub fn new(low: Freq, high: Freq, gain: f32, weight: f32) -> Band {
+        let (low, high) = (cycles(low), cycles(high));
+
+        assert!(low < high && low >= 0.0 && high <= 0.5 + 1e-6);
+        assert!(weight > 0.0);
+
+        Band {
+            low: low,
+            high: high.min(0.5),
+            gain: gain as f64,
+            weight: weight as f64,
+        }
+    }
+}
+
+/// Convert the given frequency to cycles/sample.
+fn cycles(f: Freq) -> f64 {
+    f.sample_cycles()
+}
+
+/// Design an equiripple filter with the given number of taps meeting the given bands,
+/// which must be in increasing order and not overlap. If the exchange fails to converge,
+/// the best design found is returned.
+pub fn remez(taps: usize, bands: &[Band]) -> Vec<f32> {
+    assert!(taps >= 3);
+    assert!(bands.windows(2).all(|b| b[0].high < b[1].low));
+
+    // Number of cosine terms in the amplitude response, and the number of extremal
+    // frequencies is one more than that.
+    let r = (taps + 1) / 2;
+    let odd = taps % 2 == 1;
+
+    let mut grid = Grid::new(r, bands);
+
+    // An even-length filter always has a zero at the Nyquist frequency, so its response
+    // is factored as cos(πf) times a cosine series, which is what's designed here.
+    if !odd {
+        grid.factor_cos();
+    }
+
+    // Start with extremals spread evenly over the grid.
+    let mut ext: Vec<usize> = (0..r + 1).map(|i| i * (grid.len() - 1) / r).collect();
+    let mut interp = Interp::new(&grid, &ext[..]);
+
+    for _ in 0..MAX_ITERATIONS {
+        let err: Vec<f64> = (0..grid.len()).map(|i| {
+            grid.weight[i] * (grid.desired[i] - interp.eval(grid.freq[i]))
+        }).collect();
+
+        ext = search(r, &err[..]);
+
+        if converged(&err[..], &ext[..]) {
+            break;
+        }
+
+        interp = Interp::new(&grid, &ext[..]);
+    }
+
+    // Sample the amplitude response at the DFT frequencies.
+    let amp: Vec<f64> = (0..taps / 2 + 1).map(|k| {
+        let f = k as f64 / taps as f64;
+        let c = if odd { 1.0 } else { (PI * f).cos() };
+
+        interp.eval(f) * c
+    }).collect();
+
+    freq_sample(taps, &amp[..])
+}
+
+/// Dense frequency grid over the bands along with the desired response and weight at
+/// each point.
+struct Grid {
+    freq: Vec<f64>,
+    desired: Vec<f64>,
+    weight: Vec<f64>,
+}
+
+impl Grid {
+    fn new(r: usize, bands: &[Band]) -> Grid {
+        let delf = 0.5 / (GRID_DENSITY * r) as f64;
+
+        let mut grid = Grid {
+            freq: vec![],
+            desired: vec![],
+            weight: vec![],
+        };
+
+        for band in bands {
+            let points = ((band.high - band.low) / delf + 0.5) as usize;
+            let points = std::cmp::max(points, 1);
+
+            for i in 0..points {
+                grid.freq.push(band.low + i as f64 * delf);
+                grid.desired.push(band.gain);
+                grid.weight.push(band.weight);
+            }
+
+            // Make sure the band edge itself is on the grid.
+            *grid.freq.last_mut().unwrap() = band.high;
+        }
+
+        grid
+    }
+
+    fn len(&self) -> usize {
+        self.freq.len()
+    }
+
+    /// Divide the desired response by cos(πf), keeping the weighted error the same.
+    fn factor_cos(&mut self) {
+        let delf = self.freq[1] - self.freq[0];
+        let last = self.len() - 1;
+
+        // The factored response can't be specified where cos(πf) is zero.
+        if self.freq[last] > 0.5 - delf {
+            self.freq[last] = 0.5 - delf;
+        }
+
+        for i in 0..self.len() {
+            let c = (PI * self.freq[i]).cos();
+
+            self.desired[i] /= c;
+            self.weight[i] *= c;
+        }
+    }
+}
+
+/// Barycentric Lagrange interpolation of the amplitude response that passes through the
+/// desired response with alternating error ±δ at the current extremal frequencies. The
+/// deviation δ is chosen so the interpolating polynomial has one degree less than the
+/// number of extremals, as the cosine series requires.
+struct Interp {
+    /// Extremal frequencies, as x = cos(2πf.)
+    x: Vec<f64>,
+    /// Barycentric weights.
+    ad: Vec<f64>,
+    /// Response at each extremal.
+    y: Vec<f64>,
+}
+
+impl Interp {
+    fn new(grid: &Grid, ext: &[usize]) -> Interp {
+        let x: Vec<f64> = ext.iter().map(|&e| (2.0 * PI * grid.freq[e]).cos()).collect();
+
+        // Products are taken in strides to keep the intermediate terms from overflowing
+        // or underflowing.
+        let stride = (x.len() - 2) / 15 + 1;
+
+        let ad: Vec<f64> = (0..x.len()).map(|i| {
+            let mut denom = 1.0;
+
+            for j in 0..stride {
+                for k in (j..x.len()).filter(|k| (k - j) % stride == 0) {
+                    if k != i {
+                        denom *= 2.0 * (x[i] - x[k]);
+                    }
+                }
+            }
+
+            if denom.abs() < 1e-5 {
+                denom = 1e-5;
+            }
+
+            1.0 / denom
+        }).collect();
+
+        // Solve for the deviation δ that makes the error alternate.
+        let (mut numer, mut denom) = (0.0, 0.0);
+
+        for (i, &e) in ext.iter().enumerate() {
+            let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+
+            numer += ad[i] * grid.desired[e];
+            denom += sign * ad[i] / grid.weight[e];
+        }
+
+        let delta = numer / denom;
+
+        let y = ext.iter().enumerate().map(|(i, &e)| {
+            let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+            grid.desired[e] - sign * delta / grid.weight[e]
+        }).collect();
+
+        Interp {
+            x: x,
+            ad: ad,
+            y: y,
+        }
+    }
+
+    /// Evaluate the response at the given frequency (cycles/sample.)
+    fn eval(&self, f: f64) -> f64 {
+        let xc = (2.0 * PI * f).cos();
+        let (mut numer, mut denom) = (0.0, 0.0);
+
+        for i in 0..self.x.len() {
+            let c = xc - self.x[i];
+
+            if c.abs() < 1e-7 {
+                return self.y[i];
+            }
+
+            let c = self.ad[i] / c;
+
+            numer += c * self.y[i];
+            denom += c;
+        }
+
+        numer / denom
+    }
+}
+
+/// Find the r + 1 grid indexes where the given error has its largest alternating
+/// extrema.
+fn search(r: usize, err: &[f64]) -> Vec<usize> {
+    let last = err.len() - 1;
+
+    let mut found: Vec<usize> = (0..err.len()).filter(|&i| {
+        let e = err[i];
+
+        let rising = i == 0 || (e > 0.0 && e >= err[i - 1]) || (e < 0.0 && e <= err[i - 1]);
+        let falling = i == last || (e > 0.0 && e > err[i + 1]) ||
+                      (e < 0.0 && e < err[i + 1]);
+
+        e != 0.0 && rising && falling
+    }).collect();
+
+    while found.len() > r + 1 {
+        // Two neighboring extrema with the same sign can't both be kept, so drop the
+        // smaller of the first such pair.
+        let pair = (1..found.len()).find(|&j| {
+            (err[found[j]] > 0.0) == (err[found[j - 1]] > 0.0)
+        });
+
+        let remove = match pair {
+            Some(j) => if err[found[j]].abs() < err[found[j - 1]].abs() { j } else { j - 1 },
+            // Otherwise all alternate, so drop the smaller of the ends.
+            None => if err[found[found.len() - 1]].abs() < err[found[0]].abs() {
+                found.len() - 1
+            } else {
+                0
+            },
+        };
+
+        found.remove(remove);
+    }
+
+    // Too few extrema can happen when the design is degenerate, so keep the old count
+    // by padding with the last grid point.
+    while found.len() < r + 1 {
+        found.push(last);
+    }
+
+    found
+}
+
+/// Check if the error at the extremals has (nearly) equal magnitude.
+fn converged(err: &[f64], ext: &[usize]) -> bool {
+    let mags = ext.iter().map(|&e| err[e].abs());
+
+    let min = mags.clone().fold(std::f64::INFINITY, f64::min);
+    let max = mags.fold(0.0, f64::max);
+
+    max == 0.0 || (max - min) / max < 1e-4
+}
+
+/// Compute the symmetric impulse response of the given length from samples of its
+/// amplitude response at the frequencies k/taps.
+fn freq_sample(taps: usize, amp: &[f64]) -> Vec<f32> {
+    let m = (taps - 1) as f64 / 2.0;
+
+    // For an even length, the sample at the Nyquist frequency is zero.
+    let terms = if taps % 2 == 1 { amp.len() } else { taps / 2 };
+
+    (0..taps).map(|n| {
+        let x = 2.0 * PI * (n as f64 - m) / taps as f64;
+        let sum = (1..terms).fold(amp[0], |s, k| s + 2.0 * amp[k] * (x * k as f64).cos());
+
+        (sum / taps as f64) as f32
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+
+    use freq::Freq;
+    use super::{Band, remez};
+
+    fn gain(coefs: &[f32], f: f32) -> f32 {
+        let w = 2.0 * PI * f;
+
+        let (re, im) = coefs.iter().enumerate().fold((0.0, 0.0), |(re, im), (n, &c)| {
+            (re + c * (w * n as f32).cos(), im - c * (w * n as f32).sin())
+        });
+
+        (re * re + im * im).sqrt()
+    }
+
+    #[test]
+    fn test_published() {
+        // 32-tap bandpass example from McClellan, Parks, and Rabiner, "A Computer
+        // Program for Designing Optimum FIR Linear Phase Digital Filters" (1973.)
+        const EXPECTED: &'static [f32] = &[
+            -0.0057534026, 0.00099026691, 0.0075733471, -0.0065141204, 0.013960509,
+            0.0022951644, -0.019994041, 0.0071369656, -0.039657373, 0.011260066,
+            0.066233635, -0.010497202, 0.085136160, -0.12024988, -0.29678580,
+            0.30410913,
+        ];
+
+        let sr = 1000;
+        let h = remez(32, &[
+            Band::new(Freq::new(0, sr), Freq::new(100, sr), 0.0, 10.0),
+            Band::new(Freq::new(200, sr), Freq::new(350, sr), 1.0, 1.0),
+            Band::new(Freq::new(425, sr), Freq::new(500, sr), 0.0, 10.0),
+        ]);
+
+        assert_eq!(h.len(), 32);
+
+        for (i, &e) in EXPECTED.iter().enumerate() {
+            assert!((h[i] - e).abs() < 1e-5);
+            assert!((h[31 - i] - e).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_lowpass() {
+        let sr = 10000;
+        let h = remez(41, &[
+            Band::new(Freq::new(0, sr), Freq::new(1000, sr), 1.0, 1.0),
+            Band::new(Freq::new(1500, sr), Freq::new(5000, sr), 0.0, 10.0),
+        ]);
+
+        // The passband ripple is ten times the stopband ripple.
+        let pass = (0..100).map(|i| (gain(&h[..], i as f32 * 0.001) - 1.0).abs())
+                           .fold(0.0, f32::max);
+        let stop = (0..350).map(|i| gain(&h[..], 0.15 + i as f32 * 0.001))
+                           .fold(0.0, f32::max);
+
+        assert!(stop < 0.01);
+        assert!((pass / stop - 10.0).abs() < 0.5);
+    }
+}
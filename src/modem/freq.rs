@@ -1,27 +1,103 @@
 use std;
+use std::ops::Neg;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub struct Freq {
-    // Cycles per second.
-    hz: usize,
+    // Cycles per second, which may be fractional or negative.
+    hz: f64,
     sr: usize,
 }
 
 impl Freq {
     pub fn new(hz: usize, sr: usize) -> Freq {
+        Freq::from_hz(hz as f64, sr)
+    }
+
+    // Create a frequency with a fractional or negative number of cycles per second, such
+    // as an FSK tone or an offset from a complex baseband carrier.
+    pub fn from_hz(hz: f64, sr: usize) -> Freq {
+        assert!(sr > 0);
+
         Freq {
             hz: hz,
             sr: sr,
         }
     }
 
+    // Get cycles per second.
+    pub fn hz(&self) -> f64 {
+        self.hz
+    }
+
+    // Get the sample rate the frequency is relative to.
+    pub fn sample_rate(&self) -> usize {
+        self.sr
+    }
+
+    // Get the same frequency relative to a different sample rate.
+    pub fn at_rate(&self, sr: usize) -> Freq {
+        Freq::from_hz(self.hz, sr)
+    }
+
+    // Get the frequency shifted by the given cycles per second.
+    pub fn offset(&self, hz: f64) -> Freq {
+        Freq::from_hz(self.hz + hz, self.sr)
+    }
+
     // Get radians per second.
     pub fn ang_freq(&self) -> f32 {
-        2.0 * std::f32::consts::PI * self.hz as f32
+        (2.0 * std::f64::consts::PI * self.hz) as f32
+    }
+
+    // Get cycles per sample, at full precision.
+    pub fn sample_cycles(&self) -> f64 {
+        self.hz / self.sr as f64
+    }
+
+    // Get the frequency relative to the Nyquist frequency, so 1.0 is half the sample
+    // rate.
+    pub fn normalized(&self) -> f64 {
+        2.0 * self.sample_cycles()
     }
 
     // Get radians per sample given the samples per second.
     pub fn sample_freq(&self) -> f32 {
-        self.ang_freq() / self.sr as f32
+        (2.0 * std::f64::consts::PI * self.sample_cycles()) as f32
+    }
+}
+
+impl Neg for Freq {
+    type Output = Freq;
+
+    fn neg(self) -> Freq {
+        Freq::from_hz(-self.hz, self.sr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+
+    use super::Freq;
+
+    #[test]
+    fn test_freq() {
+        let f = Freq::from_hz(1270.5, 8000);
+
+        assert_eq!(f.hz(), 1270.5);
+        assert!((f.sample_freq() - 2.0 * PI * 1270.5 / 8000.0).abs() < 1e-6);
+        assert!((f.normalized() - 1270.5 / 4000.0).abs() < 1e-9);
+        assert_eq!(Freq::new(1000, 8000), Freq::from_hz(1000.0, 8000));
+
+        // Signed offsets, such as for complex baseband.
+        let g = -f.offset(-2000.0);
+        assert_eq!(g.hz(), 729.5);
+        assert!((-f).sample_freq() < 0.0);
+
+        // The same frequency at a different rate.
+        let h = f.at_rate(44100);
+        assert_eq!(h.hz(), 1270.5);
+        assert_eq!(h.sample_rate(), 44100);
+        assert!((h.sample_cycles() - 1270.5 / 44100.0).abs() < 1e-12);
     }
 }
@@ -1,16 +1,28 @@
 #![feature(range_contains)]
 
+extern crate byteorder;
 extern crate num;
 
 pub mod carrier;
+pub mod conv;
 pub mod data;
 pub mod demodulator;
 pub mod digital;
+pub mod fft;
 pub mod fir;
 pub mod freq;
 pub mod modulator;
+pub mod nco;
 pub mod phasor;
 pub mod pll;
+pub mod prbs;
+pub mod pulse;
 pub mod rates;
+pub mod raw;
+pub mod resample;
+pub mod scramble;
+pub mod sigmf;
+pub mod timing;
+pub mod wav;
 
 mod util;
@@ -1,9 +1,12 @@
+use std::borrow::Cow;
+
 use num::complex::Complex32;
 
 use data::{Source, SourceUpdate};
 use phasor::Phasor;
 use carrier::Carrier;
 use digital::DigitalPhasor;
+use fir::FIRFilter;
 
 pub struct Modulator<'a> {
     carrier: &'a mut Carrier,
@@ -20,13 +23,14 @@ impl<'a> Modulator<'a> {
 }
 
 pub struct IQSample {
-    carrier: f32,
+    /// Sine and cosine of the carrier phase.
+    carrier: (f32, f32),
     pub i: f32,
     pub q: f32,
 }
 
 impl IQSample {
-    fn new(carrier: f32, i: f32, q: f32) -> IQSample {
+    fn new(carrier: (f32, f32), i: f32, q: f32) -> IQSample {
         IQSample {
             carrier: carrier,
             i: i,
@@ -43,7 +47,7 @@ impl IQSample {
     }
 
     pub fn modulate(&self) -> Complex32 {
-        let (sin, cos) = self.carrier.sin_cos();
+        let (sin, cos) = self.carrier;
         Complex32::new(self.real(cos, sin), self.imag(cos, sin))
     }
 }
@@ -52,10 +56,10 @@ impl<'a> Iterator for Modulator<'a> {
     type Item = IQSample;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let phase = self.carrier.next();
+        let carrier = self.carrier.next_sin_cos();
 
         match self.phasor.next(self.carrier.sample) {
-            Some((i, q)) => Some(IQSample::new(phase, i, q)),
+            Some((i, q)) => Some(IQSample::new(carrier, i, q)),
             None => None,
         }
     }
@@ -65,6 +69,15 @@ pub struct DigitalModulator<'a> {
     data: Box<Source>,
     carrier: &'a mut Carrier,
     phasor: Box<DigitalPhasor>,
+    shaping: Option<Shaping<'a>>,
+}
+
+/// Pulse-shaping filters for the baseband I/Q.
+struct Shaping<'a> {
+    i: FIRFilter<'a>,
+    q: FIRFilter<'a>,
+    /// Remaining samples to flush out of the filters once the data is finished.
+    flush: Option<usize>,
 }
 
 impl<'a> DigitalModulator<'a> {
@@ -75,15 +88,63 @@ impl<'a> DigitalModulator<'a> {
             data: src,
             carrier: c,
             phasor: phasor,
+            shaping: None,
         }
     }
+
+    /// Shape each symbol with the given pulse-shaping filter taps, such as from
+    /// `pulse::Pulse::shaping`. Rather than holding each symbol's (i, q) for the symbol
+    /// period, an impulse of it is filtered at the start of each symbol, so this only
+    /// makes sense for phasors that give a constant (i, q) per symbol.
+    pub fn set_pulse<K>(&mut self, taps: K) where K: Into<Cow<'a, [f32]>> {
+        let taps = taps.into();
+        assert!(taps.len() > 1);
+
+        self.shaping = Some(Shaping {
+            i: FIRFilter::new(taps.clone()),
+            q: FIRFilter::new(taps),
+            flush: None,
+        });
+    }
+
+    fn shaped(&mut self, carrier: (f32, f32)) -> Option<IQSample> {
+        let sample = self.carrier.sample;
+        let shaping = self.shaping.as_mut().unwrap();
+
+        let (i, q) = if let Some(n) = shaping.flush {
+            if n == 0 {
+                return None;
+            }
+
+            shaping.flush = Some(n - 1);
+            (0.0, 0.0)
+        } else {
+            match self.data.next() {
+                SourceUpdate::Finished => {
+                    shaping.flush = Some(shaping.i.coefs().len() - 2);
+                    (0.0, 0.0)
+                },
+                SourceUpdate::Changed(b) => {
+                    self.phasor.update(sample, b);
+                    self.phasor.next(sample, b)
+                },
+                SourceUpdate::Unchanged(_) => (0.0, 0.0),
+            }
+        };
+
+        Some(IQSample::new(carrier, shaping.i.add(i), shaping.q.add(q)))
+    }
 }
 
 impl<'a> Iterator for DigitalModulator<'a> {
     type Item = IQSample;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let phase = self.carrier.next();
+        let carrier = self.carrier.next_sin_cos();
+
+        if self.shaping.is_some() {
+            return self.shaped(carrier);
+        }
 
         let bits = match self.data.next() {
             SourceUpdate::Finished => return None,
@@ -96,6 +157,42 @@ impl<'a> Iterator for DigitalModulator<'a> {
 
         let (i, q) = self.phasor.next(self.carrier.sample, bits);
 
-        Some(IQSample::new(phase, i, q))
+        Some(IQSample::new(carrier, i, q))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use carrier::Carrier;
+    use data::Bits;
+    use digital::bpsk::BPSK;
+    use freq::Freq;
+    use pulse::Pulse;
+    use rates::Rates;
+    use super::DigitalModulator;
+
+    #[test]
+    fn test_shaped() {
+        const BITS: &'static [u8] = &[1, 0, 0, 1, 1, 0];
+
+        let taps = Pulse::RaisedCosine(0.5).shaping(8.0, 6);
+        let mut carrier = Carrier::new(Freq::new(1000, 8000));
+
+        let mut m = DigitalModulator::new(&mut carrier, Box::new(BPSK::new(0.0, 1.0)),
+                                          Box::new(Bits::new(BITS, Rates::new(1, 8), 1)));
+        m.set_pulse(&taps[..]);
+
+        let out: Vec<(f32, f32)> = m.map(|s| (s.i, s.q)).collect();
+
+        // Flushes the full filter response.
+        assert_eq!(out.len(), BITS.len() * 8 + taps.len() - 1);
+
+        // Hits each symbol exactly at the peak of its pulse.
+        for (k, &b) in BITS.iter().enumerate() {
+            let (i, q) = out[k * 8 + taps.len() / 2];
+
+            assert!((i - if b == 1 { 1.0 } else { -1.0 }).abs() < 1e-5);
+            assert!(q.abs() < 1e-5);
+        }
     }
 }
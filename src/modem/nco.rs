@@ -0,0 +1,229 @@
+/// Implements a numerically controlled oscillator, which generates a phase that advances
+/// by a controllable frequency each sample.
+///
+/// The phase is accumulated in cycles as an f64 and wrapped every sample, so it keeps
+/// full precision however long the stream runs, and the frequency and phase can be
+/// adjusted at any time, such as by a tracking loop. The sine and cosine can optionally
+/// be looked up in a table rather than computed.
+
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+use freq::Freq;
+
+#[derive(Clone)]
+pub struct NCO {
+    /// Current phase (cycles), in [0, 1).
+    phase: f64,
+    /// Phase advance per sample (cycles.)
+    step: f64,
+    /// One cycle of a sine for looking up the sine and cosine.
+    table: Option<Rc<Vec<f32>>>,
+}
+
+impl NCO {
+    /// Create a new oscillator at the given frequency, starting at zero phase.
+    pub fn new(freq: Freq) -> NCO {
+        NCO {
+            phase: 0.0,
+            step: freq.sample_cycles(),
+            table: None,
+        }
+    }
+
+    /// Look up the sine and cosine in a table with the given number of entries (a power
+    /// of two) per cycle, linearly interpolating between them.
+    pub fn set_table(&mut self, size: usize) {
+        assert!(size.is_power_of_two());
+
+        self.table = Some(Rc::new((0..size + 1).map(|n| {
+            (2.0 * PI * n as f64 / size as f64).sin() as f32
+        }).collect()));
+    }
+
+    /// Get the current phase (radians), in [0, 2π).
+    pub fn phase(&self) -> f32 {
+        let phase = (2.0 * PI * self.phase) as f32;
+
+        // A phase just under a full cycle can round up to 2π in f32.
+        if phase >= 2.0 * PI as f32 { 0.0 } else { phase }
+    }
+
+    /// Get the frequency (radians/sample.)
+    pub fn freq(&self) -> f32 {
+        (2.0 * PI * self.step) as f32
+    }
+
+    /// Set the frequency (radians/sample.) This takes effect on the next sample, so the
+    /// phase stays continuous.
+    pub fn set_freq(&mut self, freq: f32) {
+        self.step = freq as f64 / (2.0 * PI);
+    }
+
+    /// Retune to the given frequency, at full precision. As with `set_freq`, the phase
+    /// stays continuous.
+    pub fn tune(&mut self, freq: Freq) {
+        self.step = freq.sample_cycles();
+    }
+
+    /// Shift the frequency by the given amount (radians/sample.)
+    pub fn adjust_freq(&mut self, delta: f32) {
+        self.step += delta as f64 / (2.0 * PI);
+    }
+
+    /// Set the current phase (radians.)
+    pub fn set_phase(&mut self, phase: f32) {
+        self.phase = wrap(phase as f64 / (2.0 * PI));
+    }
+
+    /// Shift the current phase by the given amount (radians.)
+    pub fn adjust_phase(&mut self, delta: f32) {
+        self.phase = wrap(self.phase + delta as f64 / (2.0 * PI));
+    }
+
+    /// Get the current phase (radians) and advance to the next sample.
+    pub fn next(&mut self) -> f32 {
+        let phase = self.phase();
+        self.phase = wrap(self.phase + self.step);
+
+        phase
+    }
+
+    /// Advance by the given number of samples at once.
+    pub fn skip(&mut self, samples: usize) {
+        self.phase = wrap(self.phase + self.step * samples as f64);
+    }
+
+    /// Compute the sine and cosine of the current phase.
+    pub fn sin_cos(&self) -> (f32, f32) {
+        self.sin_cos_ahead(0)
+    }
+
+    /// Compute the sine and cosine of the phase the given number of samples ahead,
+    /// without advancing.
+    pub fn sin_cos_ahead(&self, samples: usize) -> (f32, f32) {
+        let phase = wrap(self.phase + self.step * samples as f64);
+
+        let table = match self.table {
+            Some(ref t) => t,
+            None => return ((2.0 * PI * phase) as f32).sin_cos(),
+        };
+
+        // The cosine leads the sine by a quarter cycle.
+        (lookup(&table[..], phase), lookup(&table[..], wrap(phase + 0.25)))
+    }
+}
+
+/// Interpolate the given table, which holds one cycle plus a wraparound entry, at the
+/// given phase (cycles.)
+fn lookup(table: &[f32], phase: f64) -> f32 {
+    let pos = phase * (table.len() - 1) as f64;
+    let idx = pos as usize;
+    let frac = (pos - idx as f64) as f32;
+
+    table[idx] + (table[idx + 1] - table[idx]) * frac
+}
+
+/// Wrap the given phase (cycles) into [0, 1).
+fn wrap(x: f64) -> f64 {
+    x - x.floor()
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+
+    use freq::Freq;
+    use super::NCO;
+
+    #[test]
+    fn test_nco() {
+        let mut nco = NCO::new(Freq::new(1000, 8000));
+
+        for n in 0..16 {
+            assert!((nco.next() - (PI / 4.0 * (n % 8) as f32)).abs() < 1e-5);
+        }
+
+        // The phase stays exact over a long stream, where f32 sample counts would have
+        // lost it.
+        let mut nco = NCO::new(Freq::new(1200, 44100));
+
+        for _ in 0..10_000_000 {
+            nco.next();
+        }
+
+        let expected = (10_000_000u64 * 1200 % 44100) as f32 / 44100.0 * 2.0 * PI;
+        assert!((nco.phase() - expected).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_adjust() {
+        let mut nco = NCO::new(Freq::new(0, 8000));
+
+        nco.set_freq(0.1);
+        nco.next();
+        nco.adjust_freq(0.1);
+        nco.next();
+        assert!((nco.phase() - 0.3).abs() < 1e-6);
+        assert!((nco.freq() - 0.2).abs() < 1e-6);
+
+        // Adjustments wrap around.
+        nco.adjust_phase(-1.0);
+        assert!((nco.phase() - (2.0 * PI - 0.7)).abs() < 1e-5);
+
+        nco.set_phase(3.0 * PI);
+        assert!((nco.phase() - PI).abs() < 1e-5);
+
+        nco.tune(Freq::new(2000, 8000));
+        nco.next();
+        assert!((nco.phase() - 1.5 * PI).abs() < 1e-5);
+        assert!((nco.freq() - PI / 2.0).abs() < 1e-6);
+
+        // A phase that rounds up to a full cycle wraps to zero.
+        nco.set_phase(-1e-9);
+        assert_eq!(nco.phase(), 0.0);
+    }
+
+    #[test]
+    fn test_skip() {
+        let mut nco = NCO::new(Freq::new(300, 8000));
+        let mut stepped = nco.clone();
+
+        nco.skip(3);
+        nco.set_freq(0.2);
+
+        for n in 0..10 {
+            let (sin, cos) = nco.sin_cos_ahead(n);
+            let expected = (2.0 * PI * 900.0 / 8000.0 + 0.2 * n as f32).sin_cos();
+
+            assert!((sin - expected.0).abs() < 1e-5);
+            assert!((cos - expected.1).abs() < 1e-5);
+        }
+
+        // Skipping matches stepping sample by sample.
+        for _ in 0..3 {
+            stepped.next();
+        }
+
+        assert!((nco.phase() - stepped.phase()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_table() {
+        let mut nco = NCO::new(Freq::new(123, 8000));
+        let mut exact = nco.clone();
+
+        nco.set_table(1024);
+
+        for _ in 0..1000 {
+            let (sin, cos) = nco.sin_cos();
+            let (esin, ecos) = exact.sin_cos();
+
+            assert!((sin - esin).abs() < 1e-4);
+            assert!((cos - ecos).abs() < 1e-4);
+
+            nco.next();
+            exact.next();
+        }
+    }
+}
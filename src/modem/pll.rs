@@ -1,23 +1,286 @@
 extern crate num;
 
-const CHANGE: f32 = 0.447214;
+use std::f32::consts::PI;
+
+use digital::{DigitalPhasor, Slicer};
+use freq::Freq;
+use nco::NCO;
+
+/// Proportional-integral loop filter, which gives a second-order tracking loop that
+/// follows both phase and frequency offsets with zero steady-state phase error.
+pub struct LoopFilter {
+    /// Proportional gain.
+    kp: f32,
+    /// Integral gain.
+    ki: f32,
+    /// Accumulated integral term.
+    integrator: f32,
+}
+
+impl LoopFilter {
+    /// Create a new loop filter with the given noise bandwidth, normalized to the rate
+    /// the filter is updated at, and damping factor (0.707 is a common choice.)
+    pub fn new(bandwidth: f32, damping: f32) -> LoopFilter {
+        let theta = bandwidth / (damping + 0.25 / damping);
+        let denom = 1.0 + 2.0 * damping * theta + theta * theta;
+
+        LoopFilter {
+            kp: 4.0 * damping * theta / denom,
+            ki: 4.0 * theta * theta / denom,
+            integrator: 0.0,
+        }
+    }
+
+    /// Filter the given error and return the correction to apply.
+    pub fn update(&mut self, err: f32) -> f32 {
+        self.integrator += self.ki * err;
+        self.kp * err + self.integrator
+    }
+
+    /// Get the integral term, which is the steady-state correction per update.
+    pub fn integrator(&self) -> f32 {
+        self.integrator
+    }
+
+    /// Get the proportional and integral gains.
+    pub fn gains(&self) -> (f32, f32) {
+        (self.kp, self.ki)
+    }
+}
 
 pub struct PLL {
-    pub phase_offset: f32,
+    /// Loop filter, whose gains steer the oscillator. The oscillator's frequency acts
+    /// as the filter's integrator.
+    filter: LoopFilter,
+    /// Phase and frequency offset from the reference carrier.
+    nco: NCO,
 }
 
 impl PLL {
-    pub fn new() -> PLL {
+    /// Create a new PLL with the given loop bandwidth, normalized to the sample rate,
+    /// and damping factor.
+    pub fn new(bandwidth: f32, damping: f32) -> PLL {
         PLL {
-            phase_offset: 0.0,
+            filter: LoopFilter::new(bandwidth, damping),
+            nco: NCO::new(Freq::new(0, 1)),
         }
     }
 
+    /// Get the estimated phase offset (radians.)
+    pub fn phase(&self) -> f32 {
+        self.nco.phase()
+    }
+
+    /// Get the estimated frequency offset (radians/sample.)
+    pub fn freq(&self) -> f32 {
+        self.nco.freq()
+    }
+
+    /// Advance the loop by one sample with the given phase error (radians.)
+    pub fn update(&mut self, err: f32) {
+        let (kp, ki) = self.filter.gains();
+
+        self.nco.adjust_freq(ki * err);
+        self.nco.adjust_phase(kp * err);
+        self.nco.next();
+    }
+
+    /// Advance the loop by one sample without a phase error, so it keeps running at the
+    /// estimated frequency offset.
+    pub fn coast(&mut self) {
+        self.nco.next();
+    }
+
+    /// Advance the loop by one sample of the given analytic signal, which is assumed to
+    /// be an unmodulated carrier.
     pub fn handle(&mut self, carrier_phase: f32, x: num::Complex<f32>) {
-        let inner = carrier_phase + self.phase_offset;
+        let inner = carrier_phase + self.phase();
         let carrier = num::Complex::new(inner.cos(), inner.sin());
         let err = (x * carrier.conj()).arg();
 
-        self.phase_offset += CHANGE * err;
+        self.update(err);
+    }
+}
+
+/// Computes the phase error of a baseband sample, which drives the tracking loop.
+pub trait PhaseDetector {
+    /// Compute the phase error (radians, approximately) of the given baseband sample.
+    fn error(&mut self, i: f32, q: f32) -> f32;
+}
+
+/// Detector for an unmodulated carrier, which should sit on the positive I axis.
+pub struct CarrierDetector;
+
+impl PhaseDetector for CarrierDetector {
+    fn error(&mut self, i: f32, q: f32) -> f32 {
+        q.atan2(i)
+    }
+}
+
+/// Costas loop detector for suppressed-carrier BPSK, QPSK, and 8PSK, which strips the
+/// modulation so the loop can lock without a carrier tone. As with any such loop, the
+/// lock has an ambiguity of a multiple of the symbol spacing.
+pub struct Costas {
+    bits_per_symbol: usize,
+    /// Rotation that brings the constellation to the detector's native position.
+    rot_cos: f32,
+    rot_sin: f32,
+}
+
+impl Costas {
+    /// Create a new Costas detector for M-PSK with the given symbol size (1, 2, or 3
+    /// bits) and phase (radians) of the first constellation point.
+    pub fn new(bits_per_symbol: usize, phase: f32) -> Costas {
+        // Each detector is simplest with the points rotated half a sector off the axes
+        // (except for BPSK, which sits on the I axis.)
+        let native = match bits_per_symbol {
+            1 => 0.0,
+            2 => PI / 4.0,
+            3 => PI / 8.0,
+            _ => panic!("Costas loop only supports BPSK, QPSK, and 8PSK"),
+        };
+
+        let rot = native - phase;
+
+        Costas {
+            bits_per_symbol: bits_per_symbol,
+            rot_cos: rot.cos(),
+            rot_sin: rot.sin(),
+        }
+    }
+}
+
+impl PhaseDetector for Costas {
+    fn error(&mut self, i: f32, q: f32) -> f32 {
+        let (i, q) = (i * self.rot_cos - q * self.rot_sin,
+                      i * self.rot_sin + q * self.rot_cos);
+        let mag = (i * i + q * q).sqrt();
+
+        if mag == 0.0 {
+            return 0.0;
+        }
+
+        // Each error is normalized so it approximates the sine of the phase error.
+        match self.bits_per_symbol {
+            1 => i * q / (mag * mag),
+            2 => (i.signum() * q - q.signum() * i) / (2.0f32.sqrt() * mag),
+            3 => {
+                let k = (PI / 8.0).tan();
+
+                let err = if i.abs() >= q.abs() {
+                    i.signum() * q - q.signum() * i * k
+                } else {
+                    i.signum() * q * k - q.signum() * i
+                };
+
+                err * (PI / 8.0).cos() / mag
+            },
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Decision-directed detector, which measures the phase of each sample against the
+/// nearest point of an arbitrary constellation.
+pub struct DecisionDirected<P: DigitalPhasor + Slicer> {
+    phasor: P,
+    bits: Vec<u8>,
+}
+
+impl<P: DigitalPhasor + Slicer> DecisionDirected<P> {
+    pub fn new(phasor: P) -> DecisionDirected<P> {
+        let bits = vec![0; Slicer::bits_per_symbol(&phasor)];
+
+        DecisionDirected {
+            phasor: phasor,
+            bits: bits,
+        }
+    }
+}
+
+impl<P: DigitalPhasor + Slicer> PhaseDetector for DecisionDirected<P> {
+    fn error(&mut self, i: f32, q: f32) -> f32 {
+        self.phasor.slice(i, q, &mut self.bits[..]);
+        let (di, dq) = self.phasor.next(0, &self.bits[..]);
+
+        let err = num::Complex::new(i, q) * num::Complex::new(di, dq).conj();
+
+        if err.norm_sqr() == 0.0 { 0.0 } else { err.arg() }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+
+    use num;
+    use digital::DigitalPhasor;
+    use digital::bpsk::BPSK;
+    use digital::mpsk::MPSK;
+    use digital::qam::QAM;
+    use digital::qpsk::QPSK;
+    use super::{PLL, PhaseDetector, Costas, DecisionDirected};
+
+    #[test]
+    fn test_pll() {
+        let mut pll = PLL::new(0.02, 0.707);
+
+        // Track a carrier offset by 0.01 radians/sample and 1 radian.
+        for s in 0..2000 {
+            let ref_phase = 0.2 * s as f32 % (2.0 * PI);
+            let x = num::Complex::from_polar(&1.0, &(ref_phase + 0.01 * s as f32 + 1.0));
+
+            pll.handle(ref_phase, x);
+        }
+
+        let expected = (0.01 * 2000.0 + 1.0) % (2.0 * PI);
+
+        assert!((pll.freq() - 0.01).abs() < 1e-4);
+        assert!((pll.phase() - expected).abs() < 1e-2);
+    }
+
+    // Run a loop over random symbols from the given phasor with a carrier frequency and
+    // phase offset, and verify it locks to within a multiple of the given ambiguity.
+    fn check<P, D>(p: &P, mut det: D, ambiguity: f32)
+        where P: DigitalPhasor, D: PhaseDetector
+    {
+        let mut pll = PLL::new(0.01, 0.707);
+        let mut state = 0x5678u32;
+        let mut bits = vec![0; p.bits_per_symbol()];
+
+        for s in 0..4000 {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+
+            for (k, b) in bits.iter_mut().enumerate() {
+                *b = (state >> (16 + k)) as u8 & 1;
+            }
+
+            let (i, q) = p.next(0, &bits[..]);
+            let offset = 0.002 * s as f32 + 0.8 - pll.phase();
+            let y = num::Complex::new(i, q) * num::Complex::from_polar(&1.0, &offset);
+
+            pll.update(det.error(y.re, y.im));
+        }
+
+        let residual = (0.002 * 4000.0 + 0.8 - pll.phase()) / ambiguity;
+
+        assert!((pll.freq() - 0.002).abs() < 1e-4);
+        assert!((residual - residual.round()).abs() * ambiguity < 0.05);
+    }
+
+    #[test]
+    fn test_costas() {
+        check(&BPSK::new(PI / 4.0, 1.0), Costas::new(1, PI / 4.0), PI);
+        check(&QPSK::new(0.0, 1.0), Costas::new(2, PI / 4.0), PI / 2.0);
+        check(&MPSK::new(2, 0.3, 1.0), Costas::new(2, 0.3), PI / 2.0);
+        check(&MPSK::new(3, 0.0, 1.0), Costas::new(3, 0.0), PI / 4.0);
+    }
+
+    #[test]
+    fn test_decision_directed() {
+        check(&QAM::new(4, 0.0, 1.0), DecisionDirected::new(QAM::new(4, 0.0, 1.0)),
+              PI / 2.0);
+        check(&MPSK::new(3, 0.1, 1.0), DecisionDirected::new(MPSK::new(3, 0.1, 1.0)),
+              PI / 4.0);
     }
 }
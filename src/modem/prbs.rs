@@ -0,0 +1,360 @@
+/// Implements the standard pseudorandom binary sequences used as link test patterns, and
+/// a checker that synchronizes to a received sequence and measures the bit error rate.
+///
+/// The checker first predicts each bit from the previous received bits, which locks onto
+/// the sequence at any point without knowing its start. Once enough predictions in a
+/// row are right, it switches to running its own generator, so each bit error is
+/// counted once rather than multiplied by the feedback taps. It won't lock with the
+/// register all zeros or all ones, so a stream stuck at either isn't taken for the
+/// pattern. A burst of errors from a dropped or inserted bit is counted as a slip, and
+/// the checker resynchronizes.
+
+/// Number of correct predictions in a row, beyond filling the register, needed to lock.
+const LOCK_BITS: usize = 32;
+
+/// Length of the blocks of bits checked for slips.
+const SLIP_BLOCK: usize = 128;
+
+/// Errors in a block that indicate a slip rather than noise. Out of sync, about half the
+/// bits are in error.
+const SLIP_ERRORS: usize = SLIP_BLOCK / 4;
+
+/// Standard PRBS patterns, named by their polynomial degree.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Pattern {
+    /// x^7 + x^6 + 1
+    PRBS7,
+    /// x^9 + x^5 + 1
+    PRBS9,
+    /// x^15 + x^14 + 1
+    PRBS15,
+    /// x^23 + x^18 + 1
+    PRBS23,
+    /// x^31 + x^28 + 1
+    PRBS31,
+}
+
+impl Pattern {
+    /// Parse a pattern name such as `prbs15`.
+    pub fn parse(name: &str) -> Option<Pattern> {
+        match name {
+            "prbs7" => Some(Pattern::PRBS7),
+            "prbs9" => Some(Pattern::PRBS9),
+            "prbs15" => Some(Pattern::PRBS15),
+            "prbs23" => Some(Pattern::PRBS23),
+            "prbs31" => Some(Pattern::PRBS31),
+            _ => None,
+        }
+    }
+
+    /// Get the polynomial taps, with bit k - 1 set for each x^k term besides the
+    /// constant.
+    fn taps(&self) -> u32 {
+        match *self {
+            Pattern::PRBS7 => 1 << 6 | 1 << 5,
+            Pattern::PRBS9 => 1 << 8 | 1 << 4,
+            Pattern::PRBS15 => 1 << 14 | 1 << 13,
+            Pattern::PRBS23 => 1 << 22 | 1 << 17,
+            Pattern::PRBS31 => 1 << 30 | 1 << 27,
+        }
+    }
+
+    pub fn degree(&self) -> usize {
+        32 - self.taps().leading_zeros() as usize
+    }
+
+    /// Number of bits before the sequence repeats.
+    pub fn period(&self) -> u64 {
+        (1 << self.degree()) - 1
+    }
+}
+
+/// Shift register of previous bits, with the bit from k steps ago in bit k - 1.
+struct Register {
+    taps: u32,
+    state: u32,
+    mask: u32,
+}
+
+impl Register {
+    fn new(pattern: Pattern, state: u32) -> Register {
+        let mask = (1 << pattern.degree()) - 1;
+
+        Register {
+            taps: pattern.taps(),
+            state: state & mask,
+            mask: mask,
+        }
+    }
+
+    /// Predict the next bit from the previous bits.
+    fn predict(&self) -> u8 {
+        ((self.state & self.taps).count_ones() & 1) as u8
+    }
+
+    fn push(&mut self, bit: u8) {
+        self.state = (self.state << 1 | bit as u32) & self.mask;
+    }
+
+    /// Check if the register holds all zeros or all ones.
+    fn uniform(&self) -> bool {
+        self.state == 0 || self.state == self.mask
+    }
+}
+
+/// Generates the bits of a pattern, starting from the all-ones state.
+pub struct Generator {
+    reg: Register,
+}
+
+impl Generator {
+    pub fn new(pattern: Pattern) -> Generator {
+        Generator {
+            reg: Register::new(pattern, !0),
+        }
+    }
+
+    pub fn next_bit(&mut self) -> u8 {
+        let bit = self.reg.predict();
+        self.reg.push(bit);
+
+        bit
+    }
+}
+
+/// Checks received bits against a pattern.
+pub struct Checker {
+    reg: Register,
+    degree: usize,
+    locked: bool,
+    /// Correct predictions in a row while acquiring.
+    run: usize,
+    bits: u64,
+    errors: u64,
+    slips: u64,
+    /// Bits and errors in the current slip detection block.
+    block_bits: usize,
+    block_errors: usize,
+}
+
+impl Checker {
+    pub fn new(pattern: Pattern) -> Checker {
+        Checker {
+            reg: Register::new(pattern, 0),
+            degree: pattern.degree(),
+            locked: false,
+            run: 0,
+            bits: 0,
+            errors: 0,
+            slips: 0,
+            block_bits: 0,
+            block_errors: 0,
+        }
+    }
+
+    /// Check the next received bit.
+    pub fn add(&mut self, bit: u8) {
+        let expected = self.reg.predict();
+
+        if !self.locked {
+            self.reg.push(bit);
+            self.run = if bit == expected { self.run + 1 } else { 0 };
+
+            // The first predictions come from a partly filled register.
+            if self.run >= self.degree + LOCK_BITS && !self.reg.uniform() {
+                self.locked = true;
+            }
+
+            return;
+        }
+
+        self.reg.push(expected);
+
+        self.bits += 1;
+        self.block_bits += 1;
+
+        if bit != expected {
+            self.errors += 1;
+            self.block_errors += 1;
+        }
+
+        if self.block_errors >= SLIP_ERRORS {
+            // Discount the bits checked out of sync and start acquiring again.
+            self.bits -= self.block_bits as u64;
+            self.errors -= self.block_errors as u64;
+            self.slips += 1;
+            self.locked = false;
+            self.run = 0;
+            self.block_bits = 0;
+            self.block_errors = 0;
+        } else if self.block_bits == SLIP_BLOCK {
+            self.block_bits = 0;
+            self.block_errors = 0;
+        }
+    }
+
+    /// Whether the checker is synchronized to the pattern.
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Number of bits checked while synchronized.
+    pub fn bits(&self) -> u64 {
+        self.bits
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// Number of times synchronization was lost.
+    pub fn slips(&self) -> u64 {
+        self.slips
+    }
+
+    /// Get the measured bit error rate.
+    pub fn ber(&self) -> f64 {
+        if self.bits == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.bits as f64
+        }
+    }
+
+    /// Get the Wilson score interval for the bit error rate at the given confidence
+    /// level, such as 0.95, which stays meaningful when few or no errors were seen.
+    pub fn interval(&self, confidence: f64) -> (f64, f64) {
+        if self.bits == 0 {
+            return (0.0, 1.0);
+        }
+
+        let n = self.bits as f64;
+        let p = self.ber();
+        let z = normal_quantile(0.5 + confidence / 2.0);
+        let z2 = z * z;
+
+        let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+        let width = z / (1.0 + z2 / n) * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+
+        ((center - width).max(0.0), (center + width).min(1.0))
+    }
+}
+
+/// Approximate the standard normal quantile function at the given probability, within
+/// about 4.5e-4 (Abramowitz and Stegun 26.2.23.)
+fn normal_quantile(p: f64) -> f64 {
+    assert!(p > 0.0 && p < 1.0);
+
+    let q = if p < 0.5 { p } else { 1.0 - p };
+    let t = (-2.0 * q.ln()).sqrt();
+
+    let x = t - (2.515517 + 0.802853 * t + 0.010328 * t * t) /
+                (1.0 + 1.432788 * t + 0.189269 * t * t + 0.001308 * t * t * t);
+
+    if p < 0.5 { -x } else { x }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Pattern, Generator, Checker, normal_quantile};
+
+    #[test]
+    fn test_generator() {
+        for &p in &[Pattern::PRBS7, Pattern::PRBS9, Pattern::PRBS15] {
+            let mut g = Generator::new(p);
+            let bits: Vec<u8> = (0..2 * p.period()).map(|_| g.next_bit()).collect();
+            let (first, second) = bits.split_at(p.period() as usize);
+
+            // Maximal length, with one more one than zero per period.
+            assert_eq!(first, second);
+            assert_eq!(first.iter().filter(|&&b| b == 1).count() as u64,
+                       (p.period() + 1) / 2);
+            // No shorter period.
+            assert!((1..p.period() as usize).all(|s| {
+                first[s..] != second[..second.len() - s]
+            }));
+        }
+
+        assert_eq!(Pattern::parse("prbs31"), Some(Pattern::PRBS31));
+        assert_eq!(Pattern::PRBS23.period(), 8388607);
+    }
+
+    #[test]
+    fn test_checker() {
+        let mut g = Generator::new(Pattern::PRBS15);
+
+        // Start partway through the sequence.
+        for _ in 0..1000 {
+            g.next_bit();
+        }
+
+        let mut c = Checker::new(Pattern::PRBS15);
+
+        for _ in 0..100 {
+            c.add(g.next_bit());
+        }
+
+        assert!(c.locked());
+        let start = c.bits();
+
+        // Each error is counted once.
+        for i in 0..10000 {
+            let bit = g.next_bit();
+            c.add(if i % 1000 == 0 { bit ^ 1 } else { bit });
+        }
+
+        assert_eq!(c.bits() - start, 10000);
+        assert_eq!(c.errors(), 10);
+        assert_eq!(c.slips(), 0);
+
+        // Dropping a bit causes a slip, then the checker locks on again.
+        g.next_bit();
+
+        for _ in 0..1000 {
+            c.add(g.next_bit());
+        }
+
+        assert!(c.locked());
+        assert_eq!(c.slips(), 1);
+        assert_eq!(c.errors(), 10);
+
+        // Constant bits, such as from a dead link, never lock.
+        for &bit in &[0, 1] {
+            let mut c = Checker::new(Pattern::PRBS15);
+
+            for _ in 0..1000 {
+                c.add(bit);
+            }
+
+            assert!(!c.locked());
+            assert_eq!(c.bits(), 0);
+        }
+    }
+
+    #[test]
+    fn test_interval() {
+        assert!((normal_quantile(0.975) - 1.96).abs() < 1e-3);
+        assert!((normal_quantile(0.05) + 1.645).abs() < 1e-3);
+
+        let mut g = Generator::new(Pattern::PRBS9);
+        let mut c = Checker::new(Pattern::PRBS9);
+
+        for _ in 0..100000 {
+            c.add(g.next_bit());
+        }
+
+        // Around the rule of three bound with no errors.
+        let (lo, hi) = c.interval(0.95);
+        assert_eq!(lo, 0.0);
+        assert!(hi > 1.0 / c.bits() as f64 && hi < 5.0 / c.bits() as f64);
+
+        for i in 0..100000 {
+            let bit = g.next_bit();
+            c.add(if i % 100 == 0 { bit ^ 1 } else { bit });
+        }
+
+        let (lo, hi) = c.interval(0.95);
+        assert!(lo < c.ber() && c.ber() < hi);
+        assert!(lo > 0.004 && hi < 0.006);
+    }
+}
@@ -0,0 +1,239 @@
+/// Implements pulse shapes for band-limiting digital modulations. The transmitter
+/// filters an impulse per symbol with the shaping taps, and the receiver filters with
+/// the receive taps, which for every shape but the Gaussian give a combined response
+/// with no intersymbol interference.
+
+use std::f32::consts::PI;
+
+/// Symbol pulse shape.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Pulse {
+    /// Raised cosine with the given roll-off factor (0 to 1.) The pulse itself is
+    /// Nyquist, so it's meant for transmit-only shaping.
+    RaisedCosine(f32),
+    /// Root-raised cosine with the given roll-off factor (0 to 1.) Nyquist when combined
+    /// with the matched filter.
+    RootRaisedCosine(f32),
+    /// Gaussian with the given bandwidth-time product.
+    Gaussian(f32),
+    /// Half a sine cycle over one symbol.
+    HalfSine,
+}
+
+impl Pulse {
+    /// Look up a pulse by name: `rrc`, `rc`, `gaussian`, or `halfsine`, with the given
+    /// roll-off factor or bandwidth-time product where the shape takes one.
+    pub fn parse(name: &str, rolloff: f32) -> Option<Pulse> {
+        match name {
+            "rrc" => Some(Pulse::RootRaisedCosine(rolloff)),
+            "rc" => Some(Pulse::RaisedCosine(rolloff)),
+            "gaussian" => Some(Pulse::Gaussian(rolloff)),
+            "halfsine" => Some(Pulse::HalfSine),
+            _ => None,
+        }
+    }
+
+    /// Compute the pulse amplitude at the given time, in symbols from the peak.
+    pub fn at(&self, t: f32) -> f32 {
+        match *self {
+            Pulse::RaisedCosine(beta) => {
+                let x = 2.0 * beta * t;
+
+                // Handle the removable singularity.
+                if (x.abs() - 1.0).abs() < 1e-6 {
+                    PI / 4.0 * sinc(1.0 / (2.0 * beta))
+                } else {
+                    sinc(t) * (PI * beta * t).cos() / (1.0 - x * x)
+                }
+            },
+            Pulse::RootRaisedCosine(beta) => {
+                let x = 4.0 * beta * t;
+
+                if t == 0.0 {
+                    1.0 - beta + 4.0 * beta / PI
+                } else if (x.abs() - 1.0).abs() < 1e-6 {
+                    let w = PI / (4.0 * beta);
+
+                    beta / 2.0f32.sqrt() *
+                        ((1.0 + 2.0 / PI) * w.sin() + (1.0 - 2.0 / PI) * w.cos())
+                } else {
+                    ((PI * t * (1.0 - beta)).sin() + x * (PI * t * (1.0 + beta)).cos()) /
+                        (PI * t * (1.0 - x * x))
+                }
+            },
+            Pulse::Gaussian(bt) => {
+                (-2.0 * (PI * bt * t).powi(2) / 2.0f32.ln()).exp()
+            },
+            Pulse::HalfSine => {
+                if t.abs() <= 0.5 { (PI * t).cos() } else { 0.0 }
+            },
+        }
+    }
+
+    /// Compute the pulse over the given number of symbols, sampled at the given, possibly
+    /// fractional, samples per symbol. There's always an odd number of taps, so the peak
+    /// lands on the center tap.
+    fn sampled(&self, sps: f32, span: usize) -> Vec<f32> {
+        let center = center(sps, span);
+
+        (0..2 * center + 1).map(|n| {
+            self.at((n as f32 - center as f32) / sps)
+        }).collect()
+    }
+
+    /// Compute the transmit filter taps over the given number of symbols, scaled so an
+    /// impulse of each symbol's amplitude peaks at that amplitude.
+    pub fn shaping(&self, sps: f32, span: usize) -> Vec<f32> {
+        let taps = self.sampled(sps, span);
+        let peak = taps[taps.len() / 2];
+
+        taps.iter().map(|x| x / peak).collect()
+    }
+
+    /// Compute the receive filter taps matched to the transmit filter, scaled so the
+    /// combined response has unity gain at the symbol instants.
+    pub fn matched(&self, sps: f32, span: usize) -> Vec<f32> {
+        let taps = self.shaping(sps, span);
+        let energy = taps.iter().fold(0.0, |s, x| s + x * x);
+
+        taps.iter().map(|x| x / energy).collect()
+    }
+
+    /// Compute the receive filter taps for this transmit pulse, scaled so the combined
+    /// response has unity gain at the symbol instants. Root-raised-cosine and half-sine
+    /// pulses are Nyquist once matched, but a raised cosine is already Nyquist and a
+    /// Gaussian only spreads further when filtered by itself, so those are just delayed
+    /// to peak at the same time as the others.
+    pub fn receive(&self, sps: f32, span: usize) -> Vec<f32> {
+        match *self {
+            Pulse::RootRaisedCosine(_) | Pulse::HalfSine => self.matched(sps, span),
+            Pulse::RaisedCosine(_) | Pulse::Gaussian(_) => {
+                let center = center(sps, span);
+                let mut taps = vec![0.0; 2 * center + 1];
+                taps[center] = 1.0;
+
+                taps
+            },
+        }
+    }
+}
+
+/// Get the index of the center tap of a pulse over the given number of symbols.
+fn center(sps: f32, span: usize) -> usize {
+    assert!(sps > 0.0 && span > 0);
+    (span as f32 * sps / 2.0) as usize
+}
+
+/// Normalized sinc function.
+fn sinc(t: f32) -> f32 {
+    if t == 0.0 { 1.0 } else { (PI * t).sin() / (PI * t) }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::{FRAC_1_SQRT_2, PI};
+
+    use super::Pulse;
+
+    // Convolve the given taps.
+    fn convolve(a: &[f32], b: &[f32]) -> Vec<f32> {
+        (0..a.len() + b.len() - 1).map(|n| {
+            (0..a.len()).filter(|&k| n >= k && n - k < b.len())
+                        .fold(0.0, |s, k| s + a[k] * b[n - k])
+        }).collect()
+    }
+
+    #[test]
+    fn test_nyquist() {
+        // Raised cosine has zero crossings at the other symbol instants.
+        let rc = Pulse::RaisedCosine(0.35).shaping(8.0, 8);
+        assert_eq!(rc.len(), 65);
+        assert!((rc[32] - 1.0).abs() < 1e-6);
+
+        for k in 1..5 {
+            assert!(rc[32 + k * 8].abs() < 1e-5);
+            assert!(rc[32 - k * 8].abs() < 1e-5);
+        }
+
+        // So does root-raised cosine filtered with its matched filter, within the
+        // truncation error.
+        let pulse = Pulse::RootRaisedCosine(0.35);
+        let y = convolve(&pulse.shaping(8.0, 12)[..], &pulse.matched(8.0, 12)[..]);
+        assert!((y[96] - 1.0).abs() < 1e-4);
+
+        for k in 1..7 {
+            assert!(y[96 + k * 8].abs() < 0.01);
+            assert!(y[96 - k * 8].abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_fractional() {
+        // With 45.45 samples per symbol, the zero crossings fall between samples at the
+        // exact symbol instants, rather than drifting off by a sample every two symbols.
+        let sps = 10000.0 / 220.0;
+        let rc = Pulse::RaisedCosine(0.35).shaping(sps, 8);
+
+        assert_eq!(rc.len(), 363);
+
+        for k in 1..4 {
+            let t = 181.0 + k as f32 * sps;
+            assert!(rc[t.floor() as usize] * rc[t.ceil() as usize] <= 0.0);
+        }
+    }
+
+    #[test]
+    fn test_receive() {
+        // Every combined response peaks at unity, and all but the Gaussian's are close to
+        // zero at the other symbol instants, within the truncation error for RRC.
+        for &(p, isi) in &[(Pulse::RootRaisedCosine(0.35), 0.02),
+                           (Pulse::RaisedCosine(0.35), 1e-5),
+                           (Pulse::HalfSine, 1e-5),
+                           (Pulse::Gaussian(0.35), 0.05)]
+        {
+            let y = convolve(&p.shaping(8.0, 8)[..], &p.receive(8.0, 8)[..]);
+            assert!((y[64] - 1.0).abs() < 1e-4);
+
+            for k in 1..8 {
+                assert!(y[64 + k * 8].abs() < isi);
+                assert!(y[64 - k * 8].abs() < isi);
+            }
+        }
+    }
+
+    #[test]
+    fn test_singularities() {
+        for &p in &[Pulse::RaisedCosine(0.5), Pulse::RootRaisedCosine(0.25)] {
+            // The special cases are continuous with the general formula.
+            for &t in &[0.25, 0.5, 1.0, 2.0] {
+                assert!((p.at(t) - p.at(t + 1e-3)).abs() < 0.01);
+            }
+
+            assert!((p.at(0.0) - p.at(1e-3)).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_shapes() {
+        // The Gaussian falls to half amplitude at ln(2)/(π·BT·√2) symbols.
+        let g = Pulse::Gaussian(0.5);
+        assert!((g.at(0.0) - 1.0).abs() < 1e-6);
+        assert!((g.at(2.0f32.ln() / (PI * 0.5 * 2.0f32.sqrt())) - 0.5).abs() < 1e-5);
+        assert!(g.at(3.0) < 1e-6);
+
+        let h = Pulse::HalfSine.shaping(4.0, 2);
+        let expected = [0.0, 0.0, 0.0, FRAC_1_SQRT_2, 1.0, FRAC_1_SQRT_2, 0.0, 0.0, 0.0];
+
+        for (&x, &e) in h.iter().zip(expected.iter()) {
+            assert!((x - e).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Pulse::parse("rrc", 0.35), Some(Pulse::RootRaisedCosine(0.35)));
+        assert_eq!(Pulse::parse("gaussian", 0.5), Some(Pulse::Gaussian(0.5)));
+        assert_eq!(Pulse::parse("halfsine", 0.35), Some(Pulse::HalfSine));
+        assert_eq!(Pulse::parse("sinc", 0.35), None);
+    }
+}
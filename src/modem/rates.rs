@@ -4,16 +4,23 @@ pub struct Rates {
     pub baud_rate: usize,
     // Samples per second.
     pub sample_rate: usize,
-    // Samples per symbol.
+    // Samples per symbol, rounded down when the rates don't divide evenly.
     pub samples_per_symbol: usize,
 }
 
 impl Rates {
     pub fn new(br: usize, sr: usize) -> Rates {
+        assert!(br > 0 && br <= sr);
+
         Rates {
             baud_rate: br,
             sample_rate: sr,
             samples_per_symbol: sr / br,
         }
     }
+
+    /// Get the exact, possibly fractional, number of samples per symbol.
+    pub fn symbol_period(&self) -> f32 {
+        self.sample_rate as f32 / self.baud_rate as f32
+    }
 }
@@ -0,0 +1,228 @@
+/// Implements reading and writing headerless sample streams in the formats used by SDR
+/// tools and audio pipelines.
+///
+/// Formats are named like `cs16le`: an optional `c` for interleaved I/Q pairs, the
+/// encoding (`u8`, `s8`, `s16`, `f32`, or `f64`), and an optional `le` or `be` byte
+/// order, which defaults to little-endian. Integer samples are scaled to and from f32 in
+/// [-1, 1], and unsigned samples are offset by half their range.
+
+use std;
+use std::io::{Read, Write};
+
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
+
+/// Encoding of each sample value.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    U8,
+    S8,
+    S16,
+    F32,
+    F64,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+/// Layout of a raw sample stream.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Format {
+    pub encoding: Encoding,
+    pub endian: Endian,
+    /// Whether the values are interleaved I/Q pairs rather than real samples.
+    pub complex: bool,
+}
+
+impl Format {
+    pub fn new(encoding: Encoding, endian: Endian, complex: bool) -> Format {
+        Format {
+            encoding: encoding,
+            endian: endian,
+            complex: complex,
+        }
+    }
+
+    /// Parse a format name such as `f32`, `s16be`, or `cu8`.
+    pub fn parse(name: &str) -> Option<Format> {
+        let (complex, name) = if name.starts_with('c') {
+            (true, &name[1..])
+        } else {
+            (false, name)
+        };
+
+        let (endian, name) = if name.ends_with("le") {
+            (Endian::Little, &name[..name.len() - 2])
+        } else if name.ends_with("be") {
+            (Endian::Big, &name[..name.len() - 2])
+        } else {
+            (Endian::Little, name)
+        };
+
+        let encoding = match name {
+            "u8" => Encoding::U8,
+            "s8" => Encoding::S8,
+            "s16" => Encoding::S16,
+            "f32" => Encoding::F32,
+            "f64" => Encoding::F64,
+            _ => return None,
+        };
+
+        Some(Format::new(encoding, endian, complex))
+    }
+
+    /// Scale of full-scale integer samples.
+    fn scale(&self) -> f32 {
+        match self.encoding {
+            Encoding::U8 | Encoding::S8 => 128.0,
+            Encoding::S16 => 32768.0,
+            Encoding::F32 | Encoding::F64 => 1.0,
+        }
+    }
+}
+
+/// Iterates over the values of a raw stream, with I/Q pairs interleaved.
+pub struct Reader<R: Read> {
+    stream: R,
+    format: Format,
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(stream: R, format: Format) -> Reader<R> {
+        Reader {
+            stream: stream,
+            format: format,
+        }
+    }
+
+    fn read(&mut self) -> std::io::Result<f32> {
+        let s = &mut self.stream;
+
+        let x = match (self.format.encoding, self.format.endian) {
+            (Encoding::U8, _) => try!(s.read_u8()) as f32 - 128.0,
+            (Encoding::S8, _) => try!(s.read_i8()) as f32,
+            (Encoding::S16, Endian::Little) => try!(s.read_i16::<LittleEndian>()) as f32,
+            (Encoding::S16, Endian::Big) => try!(s.read_i16::<BigEndian>()) as f32,
+            (Encoding::F32, Endian::Little) => try!(s.read_f32::<LittleEndian>()),
+            (Encoding::F32, Endian::Big) => try!(s.read_f32::<BigEndian>()),
+            (Encoding::F64, Endian::Little) => try!(s.read_f64::<LittleEndian>()) as f32,
+            (Encoding::F64, Endian::Big) => try!(s.read_f64::<BigEndian>()) as f32,
+        };
+
+        Ok(x / self.format.scale())
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        self.read().ok()
+    }
+}
+
+/// Writes values to a raw stream, with I/Q pairs interleaved.
+pub struct Writer<W: Write> {
+    stream: W,
+    format: Format,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(stream: W, format: Format) -> Writer<W> {
+        Writer {
+            stream: stream,
+            format: format,
+        }
+    }
+
+    /// Write the given value, clipping it to [-1, 1] for integer encodings.
+    pub fn write(&mut self, x: f32) -> std::io::Result<()> {
+        let scale = self.format.scale();
+        let int = (x * scale).round().max(-scale).min(scale - 1.0);
+
+        let s = &mut self.stream;
+
+        match (self.format.encoding, self.format.endian) {
+            (Encoding::U8, _) => s.write_u8((int + 128.0) as u8),
+            (Encoding::S8, _) => s.write_i8(int as i8),
+            (Encoding::S16, Endian::Little) => s.write_i16::<LittleEndian>(int as i16),
+            (Encoding::S16, Endian::Big) => s.write_i16::<BigEndian>(int as i16),
+            (Encoding::F32, Endian::Little) => s.write_f32::<LittleEndian>(x),
+            (Encoding::F32, Endian::Big) => s.write_f32::<BigEndian>(x),
+            (Encoding::F64, Endian::Little) => s.write_f64::<LittleEndian>(x as f64),
+            (Encoding::F64, Endian::Big) => s.write_f64::<BigEndian>(x as f64),
+        }
+    }
+
+    /// Get the underlying stream.
+    pub fn into_inner(self) -> W {
+        self.stream
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::{Format, Encoding, Endian, Reader, Writer};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Format::parse("f32"), Some(Format::new(Encoding::F32, Endian::Little,
+                                                          false)));
+        assert_eq!(Format::parse("s16be"), Some(Format::new(Encoding::S16, Endian::Big,
+                                                            false)));
+        assert_eq!(Format::parse("cu8"), Some(Format::new(Encoding::U8, Endian::Little,
+                                                          true)));
+        assert_eq!(Format::parse("cf64le"), Some(Format::new(Encoding::F64,
+                                                             Endian::Little, true)));
+        assert_eq!(Format::parse("s24"), None);
+        assert_eq!(Format::parse("cs16xx"), None);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let values: Vec<f32> = (0..64).map(|i| (i as f32 * 0.4).cos() * 0.95).collect();
+
+        for &(name, tol) in &[("cu8", 1.0 / 128.0), ("cs8", 1.0 / 128.0),
+                              ("cs16", 1.0 / 32768.0), ("s16be", 1.0 / 32768.0),
+                              ("cf32", 0.0), ("f32be", 0.0), ("cf64", 0.0)]
+        {
+            let format = Format::parse(name).unwrap();
+            let mut w = Writer::new(vec![], format);
+
+            for &x in &values {
+                w.write(x).unwrap();
+            }
+
+            let out: Vec<f32> = Reader::new(Cursor::new(w.into_inner()), format)
+                                    .collect();
+            assert_eq!(out.len(), values.len());
+
+            for (a, b) in out.iter().zip(values.iter()) {
+                assert!((a - b).abs() <= tol);
+            }
+        }
+    }
+
+    #[test]
+    fn test_encoding() {
+        let mut w = Writer::new(vec![], Format::parse("s16be").unwrap());
+        w.write(0.5).unwrap();
+        w.write(-2.0).unwrap();
+        assert_eq!(w.into_inner(), vec![0x40, 0x00, 0x80, 0x00]);
+
+        let mut w = Writer::new(vec![], Format::parse("cu8").unwrap());
+        w.write(0.0).unwrap();
+        w.write(1.0).unwrap();
+        w.write(-1.0).unwrap();
+        assert_eq!(w.into_inner(), vec![128, 255, 0]);
+
+        // A partial value at the end is dropped.
+        let format = Format::parse("s16").unwrap();
+        let r = Reader::new(Cursor::new(vec![0x00, 0x40, 0x00]), format);
+        assert_eq!(r.collect::<Vec<f32>>(), vec![0.5]);
+    }
+}
@@ -0,0 +1,314 @@
+/// Implements sample rate conversion with polyphase filter banks.
+///
+/// The input is conceptually upsampled by zero-stuffing, lowpass filtered to remove the
+/// images (or the aliases when decimating), and downsampled. The polyphase structure
+/// only computes the filter outputs that are kept, using the branch of the filter that
+/// lines up with each output.
+///
+/// Both resamplers compensate for their filter delay and flush the filter at the end of
+/// the input, so the output lines up with the input and has the converted length.
+
+use std;
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+use fir::design::Window;
+
+/// Default filter taps per branch.
+pub const TAPS_PER_PHASE: usize = 16;
+
+/// Kaiser window parameter for the prototype filters, giving ~80dB attenuation.
+const KAISER_BETA: f32 = 8.0;
+
+/// Cutoff of the prototype filters relative to the lower Nyquist frequency, leaving
+/// room for the transition band.
+const CUTOFF: f32 = 0.9;
+
+/// Number of branches for arbitrary-ratio resampling.
+const FRACTIONAL_PHASES: usize = 64;
+
+/// Resample by the rational factor L/M.
+pub struct Resampler<S: Iterator<Item = f32>> {
+    input: Input<S>,
+    /// Upsampling factor L.
+    up: usize,
+    /// Downsampling factor M.
+    down: usize,
+    /// Filter branch for each of the L phases.
+    bank: Vec<Vec<f32>>,
+    /// Current phase.
+    phase: usize,
+    /// Input samples to consume before the next output.
+    pending: usize,
+}
+
+impl<S: Iterator<Item = f32>> Resampler<S> {
+    /// Create a new resampler over the given stream from the given sample rate to the
+    /// given sample rate, with the given number of filter taps per branch when
+    /// interpolating. Decimating needs proportionally more taps for the narrower
+    /// transition band, so they're scaled up by the decimation factor.
+    pub fn new(sig: S, from: usize, to: usize, taps: usize) -> Resampler<S> {
+        let g = gcd(from, to);
+        Resampler::with_factors(sig, to / g, from / g, taps)
+    }
+
+    /// Create a new resampler that upsamples by `up` and downsamples by `down`.
+    pub fn with_factors(sig: S, up: usize, down: usize, taps: usize) -> Resampler<S> {
+        assert!(up > 0 && down > 0 && taps > 0);
+
+        let cutoff = CUTOFF * 0.5 / std::cmp::max(up, down) as f32;
+        let taps = (taps * std::cmp::max(up, down) + up - 1) / up;
+
+        // Use an odd length so the delay is a whole number of upsampled samples, padding
+        // out the last branch.
+        let len = up * taps;
+        let mut proto = prototype(len - (1 - len % 2), cutoff, up as f32);
+        proto.resize(len, 0.0);
+
+        // The first output lines up with the first input once it's delayed through the
+        // filter.
+        let delay = (len - 1) / 2;
+
+        Resampler {
+            input: Input::new(sig, taps),
+            up: up,
+            down: down,
+            bank: polyphase(&proto[..], up, taps),
+            phase: delay % up,
+            pending: delay / up + 1,
+        }
+    }
+}
+
+impl<S: Iterator<Item = f32>> Iterator for Resampler<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        for _ in 0..self.pending {
+            self.input.push();
+        }
+
+        let y = self.input.dot(&self.bank[self.phase][..]);
+
+        self.phase += self.down;
+        self.pending = self.phase / self.up;
+        self.phase %= self.up;
+
+        let total = (self.input.consumed * self.up + self.down - 1) / self.down;
+        self.input.emit(y, total)
+    }
+}
+
+/// Resample by an arbitrary ratio, by linearly interpolating between the outputs of
+/// neighboring branches of a finely divided filter bank.
+pub struct FractionalResampler<S: Iterator<Item = f32>> {
+    input: Input<S>,
+    /// Output samples per input sample.
+    ratio: f64,
+    /// Filter branch for each phase, plus one wrapping around to the next input.
+    bank: Vec<Vec<f32>>,
+    /// Position of the next output past the newest input, in input samples.
+    pos: f64,
+    /// Input samples to consume before the next output.
+    pending: usize,
+}
+
+impl<S: Iterator<Item = f32>> FractionalResampler<S> {
+    /// Create a new resampler over the given stream that outputs `ratio` samples per
+    /// input sample, with the given number of filter taps per branch when interpolating
+    /// (scaled up when decimating.)
+    pub fn new(sig: S, ratio: f64, taps: usize) -> FractionalResampler<S> {
+        assert!(ratio > 0.0 && taps > 0);
+
+        let taps = (taps as f64 / ratio.min(1.0)).ceil() as usize;
+        let phases = FRACTIONAL_PHASES;
+        let cutoff = CUTOFF * 0.5 * (ratio as f32).min(1.0) / phases as f32;
+
+        // One extra tap so the last branch is the first delayed by an input sample.
+        let proto = prototype(phases * taps + 1, cutoff, phases as f32);
+
+        FractionalResampler {
+            input: Input::new(sig, taps),
+            ratio: ratio,
+            bank: polyphase(&proto[..], phases, taps).into_iter()
+                .chain(std::iter::once((0..taps).map(|k| proto[(k + 1) * phases])
+                                                .collect()))
+                .collect(),
+            // Start at the filter delay of half the branch length, like `Resampler`.
+            pos: (taps % 2) as f64 / 2.0,
+            pending: taps / 2 + 1,
+        }
+    }
+}
+
+impl<S: Iterator<Item = f32>> Iterator for FractionalResampler<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        for _ in 0..self.pending {
+            self.input.push();
+        }
+
+        let idx = self.pos * FRACTIONAL_PHASES as f64;
+        let phase = idx.floor() as usize;
+        let frac = (idx - phase as f64) as f32;
+
+        let y = self.input.dot(&self.bank[phase][..]) * (1.0 - frac) +
+                self.input.dot(&self.bank[phase + 1][..]) * frac;
+
+        self.pos += 1.0 / self.ratio;
+        self.pending = self.pos.floor() as usize;
+        self.pos -= self.pending as f64;
+
+        let total = (self.input.consumed as f64 * self.ratio).ceil() as usize;
+        self.input.emit(y, total)
+    }
+}
+
+/// Input stream and filter history common to the resamplers.
+struct Input<S: Iterator<Item = f32>> {
+    sig: S,
+    /// Most recent input samples, newest first.
+    history: VecDeque<f32>,
+    /// Number of samples taken from the stream.
+    consumed: usize,
+    /// Number of samples output.
+    produced: usize,
+    /// Whether the stream has ended, after which zeros flush the filter.
+    finished: bool,
+}
+
+impl<S: Iterator<Item = f32>> Input<S> {
+    fn new(sig: S, taps: usize) -> Input<S> {
+        Input {
+            sig: sig,
+            history: std::iter::repeat(0.0).take(taps).collect(),
+            consumed: 0,
+            produced: 0,
+            finished: false,
+        }
+    }
+
+    fn push(&mut self) {
+        let x = match self.sig.next() {
+            Some(x) if !self.finished => {
+                self.consumed += 1;
+                x
+            },
+            _ => {
+                self.finished = true;
+                0.0
+            },
+        };
+
+        self.history.pop_back();
+        self.history.push_front(x);
+    }
+
+    fn dot(&self, taps: &[f32]) -> f32 {
+        taps.iter().zip(self.history.iter()).fold(0.0, |s, (h, x)| s + h * x)
+    }
+
+    /// Output the given sample, unless the stream has ended and the given total number
+    /// of outputs have been produced.
+    fn emit(&mut self, y: f32, total: usize) -> Option<f32> {
+        if self.finished && self.produced >= total {
+            return None;
+        }
+
+        self.produced += 1;
+        Some(y)
+    }
+}
+
+/// Design a windowed-sinc lowpass with the given taps, cutoff (cycles/sample), and DC
+/// gain.
+fn prototype(taps: usize, cutoff: f32, gain: f32) -> Vec<f32> {
+    let center = (taps - 1) as f32 / 2.0;
+
+    let coefs: Vec<f32> = (0..taps).map(|n| {
+        let t = n as f32 - center;
+        let ideal = if t == 0.0 {
+            2.0 * cutoff
+        } else {
+            (2.0 * PI * cutoff * t).sin() / (PI * t)
+        };
+
+        ideal * Window::Kaiser(KAISER_BETA).coef(n, taps)
+    }).collect();
+
+    let sum = coefs.iter().fold(0.0, |s, c| s + c);
+    coefs.iter().map(|c| c * gain / sum).collect()
+}
+
+/// Split the given prototype filter into the given number of branches with the given
+/// taps each, so branch j holds h[j], h[j + phases], ...
+fn polyphase(proto: &[f32], phases: usize, taps: usize) -> Vec<Vec<f32>> {
+    (0..phases).map(|j| {
+        (0..taps).map(|k| proto[k * phases + j]).collect()
+    }).collect()
+}
+
+/// Compute the greatest common divisor.
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+
+    use super::{Resampler, FractionalResampler, TAPS_PER_PHASE, gcd};
+
+    fn tone(hz: f32, sr: f32, n: usize) -> Vec<f32> {
+        (0..n).map(|i| (2.0 * PI * hz * i as f32 / sr).sin()).collect()
+    }
+
+    // Check the given output is the given tone, away from the edges.
+    fn check(out: &[f32], hz: f32, sr: f32) {
+        for (i, &y) in out.iter().enumerate().skip(100).take(out.len() - 200) {
+            assert!((y - (2.0 * PI * hz * i as f32 / sr).sin()).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_gcd() {
+        assert_eq!(gcd(48000, 44100), 300);
+        assert_eq!(gcd(8000, 9600), 1600);
+        assert_eq!(gcd(7, 5), 1);
+    }
+
+    #[test]
+    fn test_rational() {
+        // 9600Hz to 44.1kHz and 48kHz to 8kHz.
+        for &(from, to) in &[(9600, 44100), (48000, 8000), (8000, 48000)] {
+            let input = tone(1000.0, from as f32, 4800);
+            let out: Vec<f32> = Resampler::new(input.into_iter(), from, to,
+                                               TAPS_PER_PHASE).collect();
+
+            assert_eq!(out.len(), (4800 * to + from - 1) / from);
+            check(&out[..], 1000.0, to as f32);
+        }
+    }
+
+    #[test]
+    fn test_fractional() {
+        let ratio = 1.0 / 0.9876543;
+        let input = tone(500.0, 8000.0, 4000);
+        let out: Vec<f32> = FractionalResampler::new(input.into_iter(), ratio,
+                                                     TAPS_PER_PHASE).collect();
+
+        assert_eq!(out.len(), (4000.0 * ratio).ceil() as usize);
+        check(&out[..], 500.0, 8000.0 * ratio as f32);
+    }
+
+    #[test]
+    fn test_antialias() {
+        // A tone above the output Nyquist frequency is rejected when decimating.
+        let input = tone(5000.0, 48000.0, 9600);
+        let out: Vec<f32> = Resampler::new(input.into_iter(), 48000, 8000,
+                                           TAPS_PER_PHASE).collect();
+
+        assert!(out[100..1500].iter().all(|y| y.abs() < 0.01));
+    }
+}
@@ -0,0 +1,328 @@
+/// Implements LFSR scramblers, which whiten the transmitted bits so long runs of
+/// identical bits don't cause DC or leave clock recovery without transitions.
+///
+/// Polynomials are given as a mask with bit k - 1 set for each x^k term, besides the
+/// constant term, so x^7 + x^4 + 1 is `1 << 6 | 1 << 3`. An additive scrambler XORs the
+/// bits with a free-running sequence, so the descrambler has to start in the same state,
+/// while a multiplicative scrambler feeds back its own output, so the descrambler
+/// synchronizes itself after receiving as many bits as the polynomial's degree. A
+/// multiplicative scrambler can still lock up into sending all ones for some inputs, so
+/// it can guard against long runs of ones by inverting the next input bit.
+
+use data::{Source, SourceUpdate};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Kind {
+    Additive,
+    Multiplicative,
+}
+
+#[derive(Clone)]
+pub struct Scrambler {
+    kind: Kind,
+    taps: u32,
+    /// Previous bits, with the bit from k steps ago in bit k - 1.
+    state: u32,
+    /// Mask of the bits that fit in the register.
+    mask: u32,
+    /// Length of the run of ones on the line that inverts the next bit, or 0 for no
+    /// guard.
+    guard: usize,
+    /// Length of the current run of ones on the line.
+    ones: usize,
+}
+
+impl Scrambler {
+    /// Create an additive scrambler with the given polynomial and initial register
+    /// state.
+    pub fn additive(taps: u32, seed: u32) -> Scrambler {
+        Scrambler::new(Kind::Additive, taps, seed)
+    }
+
+    /// Create a self-synchronizing multiplicative scrambler with the given polynomial and
+    /// initial register state, which must be nonzero so all-zero input still gets
+    /// scrambled.
+    pub fn multiplicative(taps: u32, seed: u32) -> Scrambler {
+        let s = Scrambler::new(Kind::Multiplicative, taps, seed);
+        assert!(s.state != 0);
+
+        s
+    }
+
+    /// Invert the bit after each run of the given number of ones on the line, at both
+    /// the scrambler and descrambler, which keeps a multiplicative scrambler from
+    /// locking up into all ones.
+    pub fn with_guard(mut self, ones: usize) -> Scrambler {
+        assert!(self.kind == Kind::Multiplicative && ones > 0);
+        self.guard = ones;
+
+        self
+    }
+
+    fn new(kind: Kind, taps: u32, seed: u32) -> Scrambler {
+        assert!(taps != 0);
+
+        let degree = 32 - taps.leading_zeros();
+        let mask = if degree == 32 { !0 } else { (1 << degree) - 1 };
+
+        Scrambler {
+            kind: kind,
+            taps: taps,
+            state: seed & mask,
+            mask: mask,
+            guard: 0,
+            ones: 0,
+        }
+    }
+
+    /// V.22bis multiplicative scrambler, 1 + x^-14 + x^-17, which inverts the bit after
+    /// 64 ones, starting from all ones.
+    pub fn v22() -> Scrambler {
+        Scrambler::multiplicative(1 << 16 | 1 << 13, !0).with_guard(64)
+    }
+
+    /// V.32 call mode multiplicative scrambler, 1 + x^-18 + x^-23, starting from all
+    /// ones. V.32 doesn't guard against runs of ones, so neither does this.
+    pub fn v32() -> Scrambler {
+        Scrambler::multiplicative(1 << 22 | 1 << 17, !0)
+    }
+
+    /// IEEE 802.11 additive data scrambler, x^7 + x^4 + 1, starting from all ones.
+    pub fn ieee80211() -> Scrambler {
+        Scrambler::additive(1 << 6 | 1 << 3, 0x7F)
+    }
+
+    /// DVB-S energy dispersal sequence, 1 + x^14 + x^15, starting from the register
+    /// loaded with 100101010000000.
+    pub fn dvbs() -> Scrambler {
+        Scrambler::additive(1 << 14 | 1 << 13, 0b10101001)
+    }
+
+    /// Look up a preset by name: `v22`, `v32`, `80211`, or `dvbs`.
+    pub fn preset(name: &str) -> Option<Scrambler> {
+        match name {
+            "v22" => Some(Scrambler::v22()),
+            "v32" => Some(Scrambler::v32()),
+            "80211" => Some(Scrambler::ieee80211()),
+            "dvbs" => Some(Scrambler::dvbs()),
+            _ => None,
+        }
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    /// Compute the feedback bit from the tapped previous bits.
+    fn feedback(&self) -> u8 {
+        ((self.state & self.taps).count_ones() & 1) as u8
+    }
+
+    fn shift(&mut self, bit: u8) {
+        self.state = (self.state << 1 | bit as u32) & self.mask;
+    }
+
+    /// Check if the guard has seen its run of ones, restarting the count if so.
+    fn guard_hit(&mut self) -> bool {
+        if self.guard > 0 && self.ones == self.guard {
+            self.ones = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Count the given bit on the line towards the current run of ones.
+    fn count(&mut self, bit: u8) {
+        self.ones = if bit == 1 { self.ones + 1 } else { 0 };
+    }
+
+    /// Scramble the next bit.
+    pub fn scramble(&mut self, bit: u8) -> u8 {
+        let bit = if self.guard_hit() { bit ^ 1 } else { bit };
+
+        let fb = self.feedback();
+        let out = bit ^ fb;
+
+        self.count(out);
+
+        self.shift(match self.kind {
+            Kind::Additive => fb,
+            Kind::Multiplicative => out,
+        });
+
+        out
+    }
+
+    /// Descramble the next received bit.
+    pub fn descramble(&mut self, bit: u8) -> u8 {
+        let fb = self.feedback();
+        let flip = self.guard_hit() as u8;
+
+        self.count(bit);
+
+        self.shift(match self.kind {
+            Kind::Additive => fb,
+            Kind::Multiplicative => bit,
+        });
+
+        bit ^ fb ^ flip
+    }
+}
+
+/// Scrambles the bits of each symbol from the wrapped source, in order.
+pub struct Scrambled<D: Source> {
+    data: D,
+    scrambler: Scrambler,
+    bits: Vec<u8>,
+}
+
+impl<D: Source> Scrambled<D> {
+    pub fn new(data: D, scrambler: Scrambler) -> Scrambled<D> {
+        Scrambled {
+            data: data,
+            scrambler: scrambler,
+            bits: vec![],
+        }
+    }
+}
+
+impl<D: Source> Source for Scrambled<D> {
+    fn next(&mut self) -> SourceUpdate {
+        match self.data.next() {
+            SourceUpdate::Finished => SourceUpdate::Finished,
+            SourceUpdate::Changed(b) => {
+                self.bits.clear();
+
+                for &bit in b {
+                    self.bits.push(self.scrambler.scramble(bit));
+                }
+
+                SourceUpdate::Changed(&self.bits[..])
+            },
+            SourceUpdate::Unchanged(_) => SourceUpdate::Unchanged(&self.bits[..]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use data::{Bits, Source, SourceUpdate};
+    use rates::Rates;
+    use super::{Scrambler, Scrambled};
+
+    fn bits(byte: u8) -> Vec<u8> {
+        (0..8).map(|i| byte >> (7 - i) & 1).collect()
+    }
+
+    #[test]
+    fn test_sequences() {
+        // Scrambling zeros gives the raw sequence.
+        let mut s = Scrambler::ieee80211();
+        let seq: Vec<u8> = (0..16).map(|_| s.scramble(0)).collect();
+        assert_eq!(seq, [bits(0x0E), bits(0xF2)].concat());
+
+        // Start of the DVB-S PRBS, given as the bytes 03 F6 08 34 30 B8 A3 93.
+        let mut s = Scrambler::dvbs();
+        let seq: Vec<u8> = (0..24).map(|_| s.scramble(0)).collect();
+        assert_eq!(seq, [bits(0x03), bits(0xF6), bits(0x08)].concat());
+
+        // The 802.11 sequence has the maximal period.
+        let mut s = Scrambler::ieee80211();
+        let first: Vec<u8> = (0..127).map(|_| s.scramble(0)).collect();
+        let second: Vec<u8> = (0..127).map(|_| s.scramble(0)).collect();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let data: Vec<u8> = (0..200).map(|i| (i / 40 % 2 == 1) as u8).collect();
+
+        for name in &["v22", "v32", "80211", "dvbs"] {
+            let mut s = Scrambler::preset(name).unwrap();
+            let mut d = Scrambler::preset(name).unwrap();
+
+            let tx: Vec<u8> = data.iter().map(|&b| s.scramble(b)).collect();
+            let rx: Vec<u8> = tx.iter().map(|&b| d.descramble(b)).collect();
+
+            assert_eq!(rx, data);
+
+            // Runs of identical bits are broken up.
+            assert!(tx.windows(24).all(|w| w.iter().any(|&b| b != w[0])));
+        }
+    }
+
+    #[test]
+    fn test_zeros() {
+        // The nonzero seed scrambles all-zero input from the start.
+        for name in &["v22", "v32"] {
+            let mut s = Scrambler::preset(name).unwrap();
+            let tx: Vec<u8> = (0..1000).map(|_| s.scramble(0)).collect();
+
+            assert!(tx.windows(24).all(|w| w.iter().any(|&b| b != w[0])));
+        }
+    }
+
+    #[test]
+    fn test_guard() {
+        // Feed the bits that would scramble to all ones.
+        let mut s = Scrambler::v22();
+        let mut data = vec![];
+        let mut tx = vec![];
+
+        for _ in 0..200 {
+            let bit = 1 ^ s.feedback();
+
+            data.push(bit);
+            tx.push(s.scramble(bit));
+        }
+
+        // Each run stops at 64 ones, and the inversion is undone on receive.
+        assert!(tx[..64].iter().all(|&b| b == 1));
+        assert_eq!(tx[64], 0);
+        assert!(tx.windows(65).all(|w| w.contains(&0)));
+
+        let mut d = Scrambler::v22();
+        let rx: Vec<u8> = tx.iter().map(|&b| d.descramble(b)).collect();
+        assert_eq!(rx, data);
+
+        // Without the guard, the scrambler locks up.
+        let mut s = Scrambler::v32();
+
+        for _ in 0..200 {
+            let bit = 1 ^ s.feedback();
+            assert_eq!(s.scramble(bit), 1);
+        }
+    }
+
+    #[test]
+    fn test_self_sync() {
+        let data: Vec<u8> = (0..100).map(|i| (i * 7 % 3 == 0) as u8).collect();
+
+        let mut s = Scrambler::v22();
+        let tx: Vec<u8> = data.iter().map(|&b| s.scramble(b)).collect();
+
+        // Start receiving partway through, with the descrambler in the wrong state.
+        let mut d = Scrambler::v22();
+        let rx: Vec<u8> = tx[30..].iter().map(|&b| d.descramble(b)).collect();
+
+        assert_eq!(&rx[17..], &data[47..]);
+    }
+
+    #[test]
+    fn test_scrambled() {
+        const BITS: &'static [u8] = &[0, 0, 0, 0, 0, 0];
+
+        let mut s = Scrambler::ieee80211();
+        let expected: Vec<u8> = (0..6).map(|_| s.scramble(0)).collect();
+
+        let mut src = Scrambled::new(Bits::new(BITS, Rates::new(1, 2), 3),
+                                     Scrambler::ieee80211());
+
+        assert!(src.next() == SourceUpdate::Changed(&expected[0..3]));
+        assert!(src.next() == SourceUpdate::Unchanged(&expected[0..3]));
+        assert!(src.next() == SourceUpdate::Changed(&expected[3..6]));
+        assert!(src.next() == SourceUpdate::Unchanged(&expected[3..6]));
+        assert!(src.next() == SourceUpdate::Finished);
+    }
+}
@@ -0,0 +1,507 @@
+/// Implements reading and writing SigMF metadata, the JSON sidecar (`.sigmf-meta`) that
+/// describes a recording of raw samples (`.sigmf-data`.)
+///
+/// Only the fields needed to describe our own recordings are handled: the core global
+/// fields, the frequency of the first capture, and annotations with labels. The modem
+/// parameters go in the global object under the `modem` extension namespace so other
+/// SigMF tools can ignore them.
+
+use std;
+use std::io::{Read, Write};
+
+use raw::{Encoding, Endian, Format};
+
+/// Unwrap an Option, returning None from the enclosing function if there's no value.
+macro_rules! try_opt {
+    ($e:expr) => (match $e {
+        Some(x) => x,
+        None => return None,
+    })
+}
+
+/// SigMF specification version written.
+const VERSION: &'static str = "1.0.0";
+
+/// Labeled extent of samples in the recording.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Annotation {
+    /// Index of the first sample.
+    pub start: usize,
+    /// Number of samples.
+    pub count: usize,
+    pub label: String,
+}
+
+impl Annotation {
+    pub fn new(start: usize, count: usize, label: &str) -> Annotation {
+        Annotation {
+            start: start,
+            count: count,
+            label: label.to_string(),
+        }
+    }
+}
+
+/// Recording metadata.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Meta {
+    /// Layout of the samples in the data file.
+    pub datatype: Format,
+    pub sample_rate: usize,
+    /// Carrier or center frequency (Hz.)
+    pub frequency: Option<f64>,
+    pub description: Option<String>,
+    /// Digital modulation, by the name the binaries take.
+    pub modulation: Option<String>,
+    pub baud_rate: Option<usize>,
+    /// Pulse shape name, roll-off factor, and span (symbols.)
+    pub pulse: Option<(String, f32, usize)>,
+    pub annotations: Vec<Annotation>,
+}
+
+impl Meta {
+    pub fn new(datatype: Format, sample_rate: usize) -> Meta {
+        Meta {
+            datatype: datatype,
+            sample_rate: sample_rate,
+            frequency: None,
+            description: None,
+            modulation: None,
+            baud_rate: None,
+            pulse: None,
+            annotations: vec![],
+        }
+    }
+
+    /// Find the first annotation with the given label.
+    pub fn annotation(&self, label: &str) -> Option<&Annotation> {
+        self.annotations.iter().find(|a| a.label == label)
+    }
+
+    /// Write the metadata as JSON to the given stream.
+    pub fn write<W: Write>(&self, mut stream: W) -> std::io::Result<()> {
+        let mut global = vec![
+            ("core:datatype", quote(&datatype(self.datatype))),
+            ("core:sample_rate", self.sample_rate.to_string()),
+            ("core:version", quote(VERSION)),
+            ("core:extensions", "[{\"name\": \"modem\", \"version\": \"0.0.1\", \
+                                 \"optional\": true}]".to_string()),
+        ];
+
+        if let Some(ref d) = self.description {
+            global.push(("core:description", quote(d)));
+        }
+
+        if let Some(ref m) = self.modulation {
+            global.push(("modem:modulation", quote(m)));
+        }
+
+        if let Some(b) = self.baud_rate {
+            global.push(("modem:baud_rate", b.to_string()));
+        }
+
+        if let Some((ref p, rolloff, span)) = self.pulse {
+            global.push(("modem:pulse", quote(p)));
+            global.push(("modem:rolloff", rolloff.to_string()));
+            global.push(("modem:span", span.to_string()));
+        }
+
+        let mut capture = vec![("core:sample_start", "0".to_string())];
+
+        if let Some(f) = self.frequency {
+            capture.push(("core:frequency", f.to_string()));
+        }
+
+        let annotations: Vec<String> = self.annotations.iter().map(|a| {
+            object(&[("core:sample_start", a.start.to_string()),
+                     ("core:sample_count", a.count.to_string()),
+                     ("core:label", quote(&a.label))], "    ")
+        }).collect();
+
+        writeln!(stream, "{{\n  \"global\": {},\n  \"captures\": [\n    {}\n  ],\n  \
+                          \"annotations\": [\n    {}\n  ]\n}}",
+                 object(&global[..], "  "), object(&capture[..], "    "),
+                 annotations.join(",\n    "))
+    }
+
+    /// Parse the metadata from the given JSON stream.
+    pub fn read<R: Read>(mut stream: R) -> std::io::Result<Meta> {
+        let mut text = String::new();
+        try!(stream.read_to_string(&mut text));
+
+        let json = try!(Parser::new(&text).parse().ok_or(invalid("invalid JSON")));
+        let global = try!(json.get("global").ok_or(invalid("no global object")));
+
+        let datatype = try!(global.get("core:datatype").and_then(Json::string)
+                                  .and_then(parse_datatype)
+                                  .ok_or(invalid("missing or unsupported datatype")));
+        let sample_rate = try!(global.get("core:sample_rate").and_then(Json::number)
+                                     .ok_or(invalid("missing sample rate")));
+
+        let mut meta = Meta::new(datatype, sample_rate as usize);
+
+        meta.description = global.get("core:description").and_then(Json::string)
+                                 .map(String::from);
+        meta.modulation = global.get("modem:modulation").and_then(Json::string)
+                                .map(String::from);
+        meta.baud_rate = global.get("modem:baud_rate").and_then(Json::number)
+                               .map(|b| b as usize);
+
+        meta.pulse = global.get("modem:pulse").and_then(Json::string).map(|p| {
+            let rolloff = global.get("modem:rolloff").and_then(Json::number);
+            let span = global.get("modem:span").and_then(Json::number);
+
+            (p.to_string(), rolloff.unwrap_or(0.35) as f32, span.unwrap_or(8.0) as usize)
+        });
+
+        meta.frequency = json.get("captures").and_then(Json::array)
+                             .and_then(|c| c.first())
+                             .and_then(|c| c.get("core:frequency"))
+                             .and_then(Json::number);
+
+        if let Some(annotations) = json.get("annotations").and_then(Json::array) {
+            for a in annotations {
+                let start = a.get("core:sample_start").and_then(Json::number);
+                let count = a.get("core:sample_count").and_then(Json::number);
+                let label = a.get("core:label").and_then(Json::string).unwrap_or("");
+
+                if let (Some(s), Some(c)) = (start, count) {
+                    meta.annotations.push(Annotation::new(s as usize, c as usize, label));
+                }
+            }
+        }
+
+        Ok(meta)
+    }
+}
+
+/// Get the SigMF datatype name of the given format, such as `cf32_le` or `ri16_be`.
+pub fn datatype(f: Format) -> String {
+    let kind = match f.encoding {
+        Encoding::U8 => "u8",
+        Encoding::S8 => "i8",
+        Encoding::S16 => "i16",
+        Encoding::F32 => "f32",
+        Encoding::F64 => "f64",
+    };
+
+    let endian = match (f.encoding, f.endian) {
+        (Encoding::U8, _) | (Encoding::S8, _) => "",
+        (_, Endian::Little) => "_le",
+        (_, Endian::Big) => "_be",
+    };
+
+    format!("{}{}{}", if f.complex { "c" } else { "r" }, kind, endian)
+}
+
+/// Parse a SigMF datatype name.
+pub fn parse_datatype(name: &str) -> Option<Format> {
+    // The names line up with the raw format names besides the signed integers and the
+    // real prefix.
+    let name = name.replace("_", "").replace("i8", "s8").replace("i16", "s16");
+
+    if name.starts_with('r') {
+        Format::parse(&name[1..])
+    } else if name.starts_with('c') {
+        Format::parse(&name)
+    } else {
+        None
+    }
+}
+
+fn invalid(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+/// Quote and escape the given string.
+fn quote(s: &str) -> String {
+    let mut out = String::from("\"");
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+/// Format the given keys and JSON values as an object, with members on separate lines
+/// at the given indent.
+fn object(members: &[(&str, String)], indent: &str) -> String {
+    let members: Vec<String> = members.iter().map(|&(k, ref v)| {
+        format!("{}  {}: {}", indent, quote(k), v)
+    }).collect();
+
+    format!("{{\n{}\n{}}}", members.join(",\n"), indent)
+}
+
+/// Parsed JSON value.
+#[derive(Debug)]
+enum Json {
+    Null,
+    /// Either boolean, since no field reads one.
+    Bool,
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Get the member of an object with the given key.
+    fn get(&self, key: &str) -> Option<&Json> {
+        match *self {
+            Json::Object(ref members) => {
+                members.iter().find(|m| m.0 == key).map(|m| &m.1)
+            },
+            _ => None,
+        }
+    }
+
+    fn string(&self) -> Option<&str> {
+        match *self {
+            Json::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn number(&self) -> Option<f64> {
+        match *self {
+            Json::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    fn array(&self) -> Option<&Vec<Json>> {
+        match *self {
+            Json::Array(ref a) => Some(a),
+            _ => None,
+        }
+    }
+}
+
+/// Recursive-descent JSON parser.
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Parser<'a> {
+        Parser {
+            chars: text.chars().peekable(),
+        }
+    }
+
+    /// Parse a single value filling the whole text.
+    fn parse(&mut self) -> Option<Json> {
+        let value = self.value();
+        self.skip_space();
+
+        match self.chars.next() {
+            None => value,
+            Some(_) => None,
+        }
+    }
+
+    fn skip_space(&mut self) {
+        while self.chars.peek().map_or(false, |c| c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    /// Consume the given literal.
+    fn expect(&mut self, lit: &str) -> Option<()> {
+        for c in lit.chars() {
+            if self.chars.next() != Some(c) {
+                return None;
+            }
+        }
+
+        Some(())
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        self.skip_space();
+
+        match self.chars.peek().cloned() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => self.string().map(Json::String),
+            Some('t') => self.expect("true").map(|_| Json::Bool),
+            Some('f') => self.expect("false").map(|_| Json::Bool),
+            Some('n') => self.expect("null").map(|_| Json::Null),
+            Some(c) if c == '-' || c.is_digit(10) => self.number(),
+            _ => None,
+        }
+    }
+
+    fn object(&mut self) -> Option<Json> {
+        self.chars.next();
+        let mut members = vec![];
+
+        self.skip_space();
+
+        if self.chars.peek() == Some(&'}') {
+            self.chars.next();
+            return Some(Json::Object(members));
+        }
+
+        loop {
+            self.skip_space();
+            let key = try_opt!(self.string());
+
+            self.skip_space();
+            try_opt!(self.expect(":"));
+
+            members.push((key, try_opt!(self.value())));
+
+            self.skip_space();
+
+            match self.chars.next() {
+                Some(',') => {},
+                Some('}') => return Some(Json::Object(members)),
+                _ => return None,
+            }
+        }
+    }
+
+    fn array(&mut self) -> Option<Json> {
+        self.chars.next();
+        let mut items = vec![];
+
+        self.skip_space();
+
+        if self.chars.peek() == Some(&']') {
+            self.chars.next();
+            return Some(Json::Array(items));
+        }
+
+        loop {
+            items.push(try_opt!(self.value()));
+
+            self.skip_space();
+
+            match self.chars.next() {
+                Some(',') => {},
+                Some(']') => return Some(Json::Array(items)),
+                _ => return None,
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        try_opt!(self.expect("\""));
+        let mut s = String::new();
+
+        loop {
+            match try_opt!(self.chars.next()) {
+                '"' => return Some(s),
+                '\\' => s.push(match try_opt!(self.chars.next()) {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    'b' => '\x08',
+                    'f' => '\x0c',
+                    'u' => {
+                        let hex: String = (0..4).filter_map(|_| self.chars.next())
+                                                .collect();
+                        let code = try_opt!(u32::from_str_radix(&hex, 16).ok());
+
+                        // Surrogate pairs aren't combined.
+                        std::char::from_u32(code).unwrap_or('\u{fffd}')
+                    },
+                    c => c,
+                }),
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Option<Json> {
+        let mut s = String::new();
+
+        while let Some(&c) = self.chars.peek() {
+            if c.is_digit(10) || "-+.eE".contains(c) {
+                s.push(c);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+
+        s.parse().ok().map(Json::Number)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use raw::Format;
+    use super::{Meta, Annotation, Json, Parser, datatype, parse_datatype};
+
+    #[test]
+    fn test_datatype() {
+        for &(raw, sigmf) in &[("cf32", "cf32_le"), ("s16be", "ri16_be"), ("cu8", "cu8"),
+                               ("cs8", "ci8"), ("f64", "rf64_le")]
+        {
+            let f = Format::parse(raw).unwrap();
+
+            assert_eq!(datatype(f), sigmf);
+            assert_eq!(parse_datatype(sigmf), Some(f));
+        }
+
+        assert_eq!(parse_datatype("cu32_le"), None);
+    }
+
+    #[test]
+    fn test_parser() {
+        let json = Parser::new(r#" {"a": [1, -2.5e1, true, null], "b\"": "x\nA",
+                                    "c": {}} "#).parse().unwrap();
+
+        match *json.get("a").unwrap() {
+            Json::Array(ref a) => {
+                assert_eq!(a[1].number(), Some(-25.0));
+                assert_eq!(a.len(), 4);
+            },
+            _ => panic!(),
+        }
+
+        assert_eq!(json.get("b\"").unwrap().string(), Some("x\nA"));
+        assert!(json.get("c").unwrap().get("d").is_none());
+
+        assert!(Parser::new("{\"a\": 1,}").parse().is_none());
+        assert!(Parser::new("[1] 2").parse().is_none());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut meta = Meta::new(Format::parse("cf32").unwrap(), 48000);
+        meta.frequency = Some(1270.5);
+        meta.description = Some("test \"recording\"".to_string());
+        meta.modulation = Some("qpsk".to_string());
+        meta.baud_rate = Some(1200);
+        meta.pulse = Some(("rrc".to_string(), 0.25, 6));
+        meta.annotations.push(Annotation::new(0, 479, "preamble"));
+        meta.annotations.push(Annotation::new(479, 8000, "payload"));
+
+        let mut buf = vec![];
+        meta.write(&mut buf).unwrap();
+
+        assert_eq!(Meta::read(&buf[..]).unwrap(), meta);
+        assert_eq!(meta.annotation("payload").unwrap().start, 479);
+
+        // Missing optional fields are fine.
+        let meta = Meta::read(&br#"{"global": {"core:datatype": "ri16_le",
+                                               "core:sample_rate": 8000}}"#[..]).unwrap();
+        assert_eq!(meta.sample_rate, 8000);
+        assert!(meta.frequency.is_none() && meta.annotations.is_empty());
+
+        assert!(Meta::read(&b"{\"global\": {}}"[..]).is_err());
+    }
+}
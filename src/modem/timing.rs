@@ -0,0 +1,261 @@
+/// Implements symbol timing recovery, which finds the optimal sampling instant of each
+/// symbol in an oversampled baseband stream and outputs a single sample per symbol.
+/// Samples between input samples are computed with a cubic Farrow interpolator, and the
+/// sampling instant is steered by a timing error detector (TED) through a loop filter.
+
+use std::collections::VecDeque;
+
+use num::complex::Complex32;
+
+use pll::LoopFilter;
+use rates::Rates;
+
+/// Damping factor of the timing loop.
+const DAMPING: f32 = 0.707;
+
+/// Largest correction applied to a single step (symbols), which keeps the strobe moving
+/// forward and far enough into the buffer to interpolate behind it.
+const MAX_ADJUST: f32 = 0.5;
+
+/// Timing error detector used to steer the sampling instant.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Detector {
+    /// Gardner detector, which uses the sample midway between symbols and is independent
+    /// of the carrier phase.
+    Gardner,
+    /// Mueller-Müller detector, which uses one sample per symbol and its decision.
+    MuellerMuller,
+    /// Early-late detector, which compares samples a quarter symbol on either side.
+    EarlyLate,
+}
+
+/// Cubic Lagrange interpolation between `x[1]` and `x[2]` at the given fractional
+/// interval `mu` in [0, 1), computed with the Farrow structure.
+pub fn farrow(x: &[Complex32; 4], mu: f32) -> Complex32 {
+    let v3 = (x[3] - x[0]) / 6.0 + (x[1] - x[2]) / 2.0;
+    let v2 = (x[0] + x[2]) / 2.0 - x[1];
+    let v1 = x[2] - x[0] / 3.0 - x[1] / 2.0 - x[3] / 6.0;
+
+    ((v3 * mu + v2) * mu + v1) * mu + x[1]
+}
+
+pub struct TimingRecovery<S>
+    where S: Iterator<Item = (f32, f32)>
+{
+    sig: S,
+    detector: Detector,
+    samples_per_symbol: f32,
+    /// Buffered input samples, which are indexed relative to the oldest.
+    buf: VecDeque<Complex32>,
+    /// Position of the next symbol strobe in the buffer.
+    strobe: f32,
+    /// Previous symbol sample.
+    prev: Complex32,
+    filter: LoopFilter,
+}
+
+impl<S> TimingRecovery<S>
+    where S: Iterator<Item = (f32, f32)>
+{
+    /// Create a new timing recovery stage over the given baseband stream using the given
+    /// detector and loop bandwidth, which is normalized to the symbol rate. The first
+    /// strobe is placed as if the stream starts on a symbol boundary, though any offset
+    /// is tracked out.
+    pub fn new(sig: S, rates: Rates, detector: Detector, bandwidth: f32)
+        -> TimingRecovery<S>
+    {
+        let sps = rates.symbol_period();
+        assert!(sps >= 2.0);
+
+        TimingRecovery {
+            sig: sig,
+            detector: detector,
+            samples_per_symbol: sps,
+            buf: VecDeque::new(),
+            strobe: sps / 2.0 + 1.0,
+            prev: Complex32::new(0.0, 0.0),
+            filter: LoopFilter::new(bandwidth, DAMPING),
+        }
+    }
+
+    /// Interpolate the input at the given position in the buffer, which must be at least
+    /// 1 so the sample before it is available.
+    fn interp(&self, pos: f32) -> Complex32 {
+        debug_assert!(pos >= 1.0);
+        let idx = pos.floor() as usize;
+
+        farrow(&[
+            self.buf[idx - 1],
+            self.buf[idx],
+            self.buf[idx + 1],
+            self.buf[idx + 2],
+        ], pos - pos.floor())
+    }
+
+    /// Make sure samples are buffered up to the given position, returning false if the
+    /// input stream ended first.
+    fn fill(&mut self, pos: f32) -> bool {
+        while self.buf.len() < pos.floor() as usize + 3 {
+            match self.sig.next() {
+                Some((i, q)) => self.buf.push_back(Complex32::new(i, q)),
+                None => return false,
+            }
+        }
+
+        true
+    }
+
+    /// Compute the timing error for the symbol sample at the current strobe, which is
+    /// positive when the strobe is late.
+    fn error(&self, y: Complex32) -> f32 {
+        match self.detector {
+            Detector::Gardner => {
+                let mid = self.interp(self.strobe - self.samples_per_symbol / 2.0);
+                ((y - self.prev) * mid.conj()).re
+            },
+            Detector::MuellerMuller => {
+                let (d, dprev) = (decide(y), decide(self.prev));
+                (self.prev * d.conj() - y * dprev.conj()).re
+            },
+            Detector::EarlyLate => {
+                let offset = self.samples_per_symbol / 4.0;
+                let early = self.interp(self.strobe - offset);
+                let late = self.interp(self.strobe + offset);
+
+                ((early - late) * y.conj()).re
+            },
+        }
+    }
+}
+
+/// Make a hard decision on each component of the given sample.
+fn decide(x: Complex32) -> Complex32 {
+    Complex32::new(x.re.signum(), x.im.signum())
+}
+
+impl<S> Iterator for TimingRecovery<S>
+    where S: Iterator<Item = (f32, f32)>
+{
+    type Item = (f32, f32);
+
+    fn next(&mut self) -> Option<(f32, f32)> {
+        let lookahead = self.strobe + self.samples_per_symbol / 4.0;
+
+        if !self.fill(lookahead) {
+            return None;
+        }
+
+        let y = self.interp(self.strobe);
+        let adjust = self.filter.update(self.error(y)).max(-MAX_ADJUST).min(MAX_ADJUST);
+
+        // Step to the next symbol, correcting by the filtered error (in symbols).
+        self.strobe += self.samples_per_symbol * (1.0 - adjust);
+        self.prev = y;
+
+        // Drop samples no longer needed for interpolating around the next strobe,
+        // including any that haven't been buffered yet.
+        while self.strobe > self.samples_per_symbol / 2.0 + 2.0 {
+            if self.buf.pop_front().is_none() {
+                self.sig.next();
+            }
+
+            self.strobe -= 1.0;
+        }
+
+        Some((y.re, y.im))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use num::complex::Complex32;
+
+    use rates::Rates;
+    use super::{farrow, Detector, TimingRecovery};
+
+    #[test]
+    fn test_farrow() {
+        // A cubic is interpolated exactly.
+        let f = |t: f32| Complex32::new(t * t * t - 2.0 * t, 0.5 * t * t);
+        let x = [f(-1.0), f(0.0), f(1.0), f(2.0)];
+
+        for &mu in &[0.0, 0.25, 0.5, 0.9] {
+            assert!((farrow(&x, mu) - f(mu)).norm() < 1e-5);
+        }
+    }
+
+    // Generate a baseband BPSK/QPSK-like stream with linear transitions between symbols,
+    // starting the given number of samples into the first symbol.
+    fn signal(sps: usize, offset: usize, symbols: usize) -> Vec<(f32, f32)> {
+        let mut state = 0x1234u32;
+        let mut prev = (1.0, -1.0);
+        let mut out = vec![];
+
+        for _ in 0..symbols {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            let cur = (if state & 0x10000 == 0 { 1.0 } else { -1.0 },
+                       if state & 0x20000 == 0 { 1.0 } else { -1.0 });
+
+            for s in 0..sps {
+                let t = s as f32 / sps as f32;
+                out.push((prev.0 + (cur.0 - prev.0) * t, prev.1 + (cur.1 - prev.1) * t));
+            }
+
+            prev = cur;
+        }
+
+        out.split_off(offset)
+    }
+
+    fn check(detector: Detector, sps: usize) {
+        let tr = TimingRecovery::new(signal(sps, 3, 600).into_iter(),
+                                     Rates::new(1, sps), detector, 0.02);
+
+        let out: Vec<(f32, f32)> = tr.collect();
+
+        // No symbols are gained or lost.
+        assert!(out.len() >= 597 && out.len() <= 600);
+
+        // Once locked, every sample lands on a symbol.
+        for &(i, q) in &out[300..] {
+            assert!((i.abs() - 1.0).abs() < 0.1);
+            assert!((q.abs() - 1.0).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn test_wide_loop() {
+        // A loop wide enough to overcorrect on a large signal still keeps the strobe
+        // within the buffer.
+        let sig: Vec<(f32, f32)> = signal(8, 3, 200).iter().map(|&(i, q)| {
+            (i * 100.0, q * 100.0)
+        }).collect();
+
+        for &detector in &[Detector::Gardner, Detector::MuellerMuller,
+                           Detector::EarlyLate]
+        {
+            let tr = TimingRecovery::new(sig.clone().into_iter(), Rates::new(1, 8),
+                                         detector, 0.9);
+
+            assert!(tr.count() > 0);
+        }
+    }
+
+    #[test]
+    fn test_gardner() {
+        check(Detector::Gardner, 8);
+        check(Detector::Gardner, 45);
+    }
+
+    #[test]
+    fn test_mueller_muller() {
+        check(Detector::MuellerMuller, 8);
+        check(Detector::MuellerMuller, 45);
+    }
+
+    #[test]
+    fn test_early_late() {
+        check(Detector::EarlyLate, 8);
+        check(Detector::EarlyLate, 45);
+    }
+}
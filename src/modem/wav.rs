@@ -0,0 +1,446 @@
+/// Implements reading and writing RIFF/WAV files of PCM or float samples.
+///
+/// Samples are converted to and from f32 in [-1, 1], interleaved by channel, so a mono
+/// file is a real waveform and a stereo file can hold I/Q pairs. The data size in the
+/// header is patched when a seekable file is finished, and otherwise left at its maximum
+/// so the file can be streamed, which readers take to mean the data runs to the end.
+/// Float files also carry the extended fmt chunk and fact chunk that non-PCM formats
+/// require.
+
+use std;
+use std::io::{Read, Write, Seek, SeekFrom};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+/// Format tags in the fmt chunk.
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Data size for a stream whose length isn't known.
+const UNKNOWN_SIZE: u32 = 0xFFFFFFFF;
+
+/// Sample encoding.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Format {
+    /// Unsigned 8-bit PCM.
+    PCM8,
+    /// Signed 16-bit PCM.
+    PCM16,
+    /// Signed 24-bit PCM.
+    PCM24,
+    /// Signed 32-bit PCM.
+    PCM32,
+    /// 32-bit float.
+    Float32,
+}
+
+impl Format {
+    /// Get the bytes per sample.
+    pub fn bytes(&self) -> usize {
+        match *self {
+            Format::PCM8 => 1,
+            Format::PCM16 => 2,
+            Format::PCM24 => 3,
+            Format::PCM32 | Format::Float32 => 4,
+        }
+    }
+
+    /// Check if the format is PCM, which gets the basic fmt chunk with no fact chunk.
+    fn is_pcm(&self) -> bool {
+        self.tag() == FORMAT_PCM
+    }
+
+    fn tag(&self) -> u16 {
+        match *self {
+            Format::Float32 => FORMAT_FLOAT,
+            _ => FORMAT_PCM,
+        }
+    }
+
+    fn from_tag(tag: u16, bits: u16) -> Option<Format> {
+        match (tag, bits) {
+            (FORMAT_PCM, 8) => Some(Format::PCM8),
+            (FORMAT_PCM, 16) => Some(Format::PCM16),
+            (FORMAT_PCM, 24) => Some(Format::PCM24),
+            (FORMAT_PCM, 32) => Some(Format::PCM32),
+            (FORMAT_FLOAT, 32) => Some(Format::Float32),
+            _ => None,
+        }
+    }
+
+    /// Scale of full-scale PCM samples.
+    fn scale(&self) -> f32 {
+        match *self {
+            Format::PCM8 => 128.0,
+            Format::PCM16 => 32768.0,
+            Format::PCM24 => 8388608.0,
+            Format::PCM32 => 2147483648.0,
+            Format::Float32 => 1.0,
+        }
+    }
+}
+
+/// Layout of the samples in a file.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Spec {
+    pub format: Format,
+    /// Interleaved channels per frame.
+    pub channels: usize,
+    /// Frames per second.
+    pub sample_rate: usize,
+}
+
+impl Spec {
+    pub fn new(format: Format, channels: usize, sample_rate: usize) -> Spec {
+        Spec {
+            format: format,
+            channels: channels,
+            sample_rate: sample_rate,
+        }
+    }
+}
+
+/// Iterates over the interleaved samples of a WAV stream.
+pub struct WavReader<R: Read> {
+    stream: R,
+    spec: Spec,
+    /// Bytes left in the data chunk, or None if it runs to the end of the stream.
+    remaining: Option<usize>,
+}
+
+impl<R: Read> WavReader<R> {
+    /// Parse the header of the given stream, leaving it at the start of the samples.
+    pub fn new(mut stream: R) -> std::io::Result<WavReader<R>> {
+        if try!(read_id(&mut stream)) != *b"RIFF" {
+            return Err(invalid("not a RIFF file"));
+        }
+
+        try!(stream.read_u32::<LittleEndian>());
+
+        if try!(read_id(&mut stream)) != *b"WAVE" {
+            return Err(invalid("not a WAVE file"));
+        }
+
+        let mut spec = None;
+
+        loop {
+            let id = try!(read_id(&mut stream));
+            let size = try!(stream.read_u32::<LittleEndian>());
+
+            match &id {
+                b"fmt " => spec = Some(try!(read_fmt(&mut stream, size as usize))),
+                b"data" => {
+                    let spec = match spec {
+                        Some(s) => s,
+                        None => return Err(invalid("data before fmt chunk")),
+                    };
+
+                    return Ok(WavReader {
+                        stream: stream,
+                        spec: spec,
+                        remaining: if size == UNKNOWN_SIZE {
+                            None
+                        } else {
+                            Some(size as usize)
+                        },
+                    });
+                },
+                // Chunks are padded to an even size.
+                _ => try!(skip(&mut stream, size as usize + size as usize % 2)),
+            }
+        }
+    }
+
+    /// Get the layout of the samples.
+    pub fn spec(&self) -> Spec {
+        self.spec
+    }
+
+    fn read_sample(&mut self) -> std::io::Result<f32> {
+        let format = self.spec.format;
+
+        let x = match format {
+            Format::PCM8 => try!(self.stream.read_u8()) as f32 - 128.0,
+            Format::PCM16 => try!(self.stream.read_i16::<LittleEndian>()) as f32,
+            Format::PCM24 => try!(self.stream.read_int::<LittleEndian>(3)) as f32,
+            Format::PCM32 => try!(self.stream.read_i32::<LittleEndian>()) as f32,
+            Format::Float32 => try!(self.stream.read_f32::<LittleEndian>()),
+        };
+
+        Ok(x / format.scale())
+    }
+}
+
+impl<R: Read> Iterator for WavReader<R> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let bytes = self.spec.format.bytes();
+
+        if let Some(r) = self.remaining {
+            if r < bytes {
+                return None;
+            }
+
+            self.remaining = Some(r - bytes);
+        }
+
+        self.read_sample().ok()
+    }
+}
+
+/// Writes interleaved samples as a WAV stream.
+pub struct WavWriter<W: Write> {
+    stream: W,
+    spec: Spec,
+    /// Bytes of samples written.
+    written: usize,
+    /// Bytes of header before the samples.
+    header: usize,
+}
+
+impl<W: Write> WavWriter<W> {
+    /// Write the header for samples of the given layout to the given stream.
+    pub fn new(mut stream: W, spec: Spec) -> std::io::Result<WavWriter<W>> {
+        let bytes = spec.format.bytes();
+        let pcm = spec.format.is_pcm();
+
+        try!(stream.write_all(b"RIFF"));
+        try!(stream.write_u32::<LittleEndian>(UNKNOWN_SIZE));
+        try!(stream.write_all(b"WAVE"));
+
+        try!(stream.write_all(b"fmt "));
+        try!(stream.write_u32::<LittleEndian>(if pcm { 16 } else { 18 }));
+        try!(stream.write_u16::<LittleEndian>(spec.format.tag()));
+        try!(stream.write_u16::<LittleEndian>(spec.channels as u16));
+        try!(stream.write_u32::<LittleEndian>(spec.sample_rate as u32));
+        try!(stream.write_u32::<LittleEndian>((spec.sample_rate * spec.channels * bytes)
+                                              as u32));
+        try!(stream.write_u16::<LittleEndian>((spec.channels * bytes) as u16));
+        try!(stream.write_u16::<LittleEndian>(8 * bytes as u16));
+
+        if !pcm {
+            // No extension beyond the size field itself.
+            try!(stream.write_u16::<LittleEndian>(0));
+
+            // Frames in the file, patched in along with the data size.
+            try!(stream.write_all(b"fact"));
+            try!(stream.write_u32::<LittleEndian>(4));
+            try!(stream.write_u32::<LittleEndian>(UNKNOWN_SIZE));
+        }
+
+        try!(stream.write_all(b"data"));
+        try!(stream.write_u32::<LittleEndian>(UNKNOWN_SIZE));
+
+        Ok(WavWriter {
+            stream: stream,
+            spec: spec,
+            written: 0,
+            header: if pcm { 44 } else { 58 },
+        })
+    }
+
+    /// Write the given sample, clipping it to [-1, 1] for PCM formats.
+    pub fn write(&mut self, x: f32) -> std::io::Result<()> {
+        let format = self.spec.format;
+        // Full scale positive is one step short of the negative, which only f64 can
+        // hold exactly for 32-bit samples.
+        let scale = format.scale() as f64;
+        let pcm = (x as f64 * scale).round().max(-scale).min(scale - 1.0);
+
+        try!(match format {
+            Format::PCM8 => self.stream.write_u8((pcm + 128.0) as u8),
+            Format::PCM16 => self.stream.write_i16::<LittleEndian>(pcm as i16),
+            Format::PCM24 => self.stream.write_int::<LittleEndian>(pcm as i64, 3),
+            Format::PCM32 => self.stream.write_i32::<LittleEndian>(pcm as i32),
+            Format::Float32 => self.stream.write_f32::<LittleEndian>(x),
+        });
+
+        self.written += format.bytes();
+
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Fill in the sizes in the header and return the stream.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        // The data chunk is padded to an even size.
+        if self.written % 2 == 1 {
+            try!(self.stream.write_u8(0));
+        }
+
+        let data = self.written as u32;
+        let riff = self.header as u32 - 8 + data + data % 2;
+
+        try!(self.stream.seek(SeekFrom::Start(4)));
+        try!(self.stream.write_u32::<LittleEndian>(riff));
+
+        if !self.spec.format.is_pcm() {
+            let frames = self.written / self.spec.format.bytes() / self.spec.channels;
+
+            try!(self.stream.seek(SeekFrom::Start(self.header as u64 - 12)));
+            try!(self.stream.write_u32::<LittleEndian>(frames as u32));
+        }
+
+        try!(self.stream.seek(SeekFrom::Start(self.header as u64 - 4)));
+        try!(self.stream.write_u32::<LittleEndian>(data));
+        try!(self.stream.seek(SeekFrom::End(0)));
+
+        Ok(self.stream)
+    }
+}
+
+fn invalid(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+fn read_id<R: Read>(stream: &mut R) -> std::io::Result<[u8; 4]> {
+    let mut id = [0; 4];
+    try!(stream.read_exact(&mut id));
+
+    Ok(id)
+}
+
+fn skip<R: Read>(stream: &mut R, bytes: usize) -> std::io::Result<()> {
+    let skipped = try!(std::io::copy(&mut stream.take(bytes as u64),
+                                     &mut std::io::sink()));
+
+    if skipped as usize == bytes {
+        Ok(())
+    } else {
+        Err(invalid("truncated chunk"))
+    }
+}
+
+/// Parse a fmt chunk of the given size.
+fn read_fmt<R: Read>(stream: &mut R, size: usize) -> std::io::Result<Spec> {
+    if size < 16 {
+        return Err(invalid("fmt chunk too short"));
+    }
+
+    let mut tag = try!(stream.read_u16::<LittleEndian>());
+    let channels = try!(stream.read_u16::<LittleEndian>());
+    let sample_rate = try!(stream.read_u32::<LittleEndian>());
+
+    // Skip the byte rate and block alignment, which follow from the rest.
+    try!(skip(stream, 6));
+
+    let bits = try!(stream.read_u16::<LittleEndian>());
+    let mut rest = size - 16;
+
+    // The extensible format holds the real tag at the start of its subformat GUID.
+    if tag == FORMAT_EXTENSIBLE && rest >= 10 {
+        try!(skip(stream, 8));
+        tag = try!(stream.read_u16::<LittleEndian>());
+        rest -= 10;
+    }
+
+    try!(skip(stream, rest + size % 2));
+
+    let format = match Format::from_tag(tag, bits) {
+        Some(f) => f,
+        None => return Err(invalid("unsupported sample format")),
+    };
+
+    if channels == 0 {
+        return Err(invalid("no channels"));
+    }
+
+    Ok(Spec::new(format, channels as usize, sample_rate as usize))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::{Format, Spec, WavReader, WavWriter};
+
+    #[test]
+    fn test_roundtrip() {
+        let samples: Vec<f32> = (0..101).map(|i| (i as f32 * 0.3).sin() * 0.9).collect();
+
+        for &(format, tol) in &[(Format::PCM8, 1.0 / 64.0), (Format::PCM16, 1e-4),
+                                (Format::PCM24, 1e-6), (Format::PCM32, 1e-6),
+                                (Format::Float32, 0.0)]
+        {
+            let spec = Spec::new(format, 1, 44100);
+            let mut w = WavWriter::new(Cursor::new(vec![]), spec).unwrap();
+
+            for &x in &samples {
+                w.write(x).unwrap();
+            }
+
+            let buf = w.finish().unwrap().into_inner();
+
+            // The header holds the exact sizes, and float files count the frames.
+            let data = samples.len() * format.bytes();
+            let header = if format == Format::Float32 { 58 } else { 44 };
+
+            assert_eq!(buf.len(), header + data + data % 2);
+            assert_eq!(&buf[header - 4..header], &[data as u8, (data >> 8) as u8, 0, 0]);
+
+            if format == Format::Float32 {
+                assert_eq!(&buf[36..50], b"\x00\x00fact\x04\x00\x00\x00\x65\x00\x00\x00");
+            }
+
+            let r = WavReader::new(Cursor::new(buf)).unwrap();
+            assert_eq!(r.spec(), spec);
+
+            let out: Vec<f32> = r.collect();
+            assert_eq!(out.len(), samples.len());
+
+            for (a, b) in out.iter().zip(samples.iter()) {
+                assert!((a - b).abs() <= tol);
+            }
+        }
+    }
+
+    #[test]
+    fn test_clip() {
+        let clip = |format| {
+            let mut w = WavWriter::new(Cursor::new(vec![]), Spec::new(format, 2, 8000))
+                .unwrap();
+
+            for &x in &[2.0, -2.0, 1.0, -1.0] {
+                w.write(x).unwrap();
+            }
+
+            w.finish().unwrap().into_inner()[44..].to_vec()
+        };
+
+        assert_eq!(clip(Format::PCM16), [0xFF, 0x7F, 0x00, 0x80, 0xFF, 0x7F, 0x00, 0x80]);
+        assert_eq!(clip(Format::PCM32), [0xFF, 0xFF, 0xFF, 0x7F, 0x00, 0x00, 0x00, 0x80,
+                                         0xFF, 0xFF, 0xFF, 0x7F, 0x00, 0x00, 0x00, 0x80]);
+    }
+
+    #[test]
+    fn test_stream() {
+        // Unfinished streams run to the end, skipping unknown chunks.
+        let mut buf = vec![];
+        buf.extend_from_slice(b"RIFF\xff\xff\xff\xffWAVE");
+        buf.extend_from_slice(b"LIST\x03\x00\x00\x00abc\x00");
+        buf.extend_from_slice(b"fmt \x10\x00\x00\x00\x01\x00\x02\x00\x40\x1f\x00\x00");
+        buf.extend_from_slice(b"\x00\x7d\x00\x00\x04\x00\x10\x00");
+        buf.extend_from_slice(b"data\xff\xff\xff\xff\x00\x40\x00\xc0\x00\x00");
+
+        let r = WavReader::new(Cursor::new(buf)).unwrap();
+        assert_eq!(r.spec(), Spec::new(Format::PCM16, 2, 8000));
+
+        let out: Vec<f32> = r.collect();
+        assert_eq!(out, vec![0.5, -0.5, 0.0]);
+
+        // An empty data chunk ends before any trailing chunks.
+        let mut buf = vec![];
+        buf.extend_from_slice(b"RIFF\x30\x00\x00\x00WAVE");
+        buf.extend_from_slice(b"fmt \x10\x00\x00\x00\x01\x00\x02\x00\x40\x1f\x00\x00");
+        buf.extend_from_slice(b"\x00\x7d\x00\x00\x04\x00\x10\x00");
+        buf.extend_from_slice(b"data\x00\x00\x00\x00LIST\x04\x00\x00\x00abcd");
+
+        let r = WavReader::new(Cursor::new(buf)).unwrap();
+        assert_eq!(r.count(), 0);
+
+        assert!(WavReader::new(Cursor::new(b"RIFX".to_vec())).is_err());
+    }
+}
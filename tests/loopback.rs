@@ -0,0 +1,138 @@
+// Sends bits through the modulate and demodulate binaries and checks they come back
+// unchanged, over the modulations and link settings demodulate can receive.
+
+use std::env;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::thread;
+
+// Bits sent in each case, a multiple of every symbol size tested.
+const BITS: usize = 1200;
+
+// Get the path to the given binary, which cargo builds in the directory above the
+// test's own.
+fn binary(name: &str) -> PathBuf {
+    let mut path = env::current_exe().unwrap();
+    path.pop();
+
+    if path.ends_with("deps") {
+        path.pop();
+    }
+
+    path.join(name)
+}
+
+// Run the given binary with the given arguments on the given input.
+fn run(name: &str, args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(binary(name)).args(args)
+                                              .stdin(Stdio::piped())
+                                              .stdout(Stdio::piped())
+                                              .stderr(Stdio::null())
+                                              .spawn().unwrap();
+
+    // Write from another thread, so a full output pipe can't block the input.
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    let writer = thread::spawn(move || { stdin.write_all(&input[..]).ok(); });
+
+    let out = child.wait_with_output().unwrap();
+    writer.join().unwrap();
+
+    out
+}
+
+// Generate pseudorandom bits as ASCII.
+fn bits() -> String {
+    let mut state = 0x1234u32;
+
+    (0..BITS).map(|_| {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        if state >> 16 & 1 == 1 { '1' } else { '0' }
+    }).collect()
+}
+
+// Send bits through both binaries with the given arguments, and verify they're all
+// received without errors.
+fn check(args: &[&str]) {
+    let sent = bits();
+
+    let wave = run("modulate", args, sent.as_bytes());
+    assert!(wave.status.success(), "modulate failed with {:?}", args);
+
+    let out = run("demodulate", args, &wave.stdout[..]);
+    assert!(out.status.success(), "demodulate failed with {:?}", args);
+
+    let received: String = String::from_utf8(out.stdout).unwrap()
+                                                        .split_whitespace().collect();
+    assert_eq!(received.len(), sent.len(), "bits lost with {:?}", args);
+
+    let errors = sent.bytes().zip(received.bytes()).filter(|&(a, b)| a != b).count();
+    assert!(errors == 0, "{} bit errors with {:?}", errors, args);
+}
+
+#[test]
+fn test_constellations() {
+    for m in &["bask", "bpsk", "qpsk", "qam16", "qam32", "qam64", "qam256", "16psk",
+               "16apsk"]
+    {
+        check(&["-m", m, "-p", "20"]);
+    }
+
+    // Without a preamble, the tracking loop finds the carrier phase on its own.
+    for m in &["qpsk", "qam64", "16psk"] {
+        check(&["-m", m]);
+    }
+}
+
+#[test]
+fn test_high_order() {
+    for m in &["qam1024", "qam4096"] {
+        check(&["-m", m, "-p", "20"]);
+    }
+}
+
+#[test]
+fn test_baud_rate() {
+    for m in &["qpsk", "qam64", "qam256", "16psk"] {
+        check(&["-m", m, "-p", "20", "-b", "50"]);
+    }
+
+    check(&["-m", "qam16", "-p", "20", "-r", "48000", "-b", "1200", "-c", "6000"]);
+}
+
+#[test]
+fn test_pulses() {
+    check(&["-m", "qam16", "-p", "20", "--pulse", "rrc"]);
+    check(&["-m", "qam64", "-p", "20", "-b", "200", "--pulse", "rc"]);
+    check(&["-m", "16psk", "-p", "20", "-b", "50", "--pulse", "halfsine"]);
+    check(&["-m", "qam16", "-p", "20", "--pulse", "gaussian", "--rolloff", "0.5"]);
+}
+
+#[test]
+fn test_coding() {
+    check(&["-m", "qam16", "-p", "20", "--fec", "1/2", "--scramble", "v22"]);
+    check(&["-m", "qpsk", "-p", "20", "--fec", "3/4", "--scramble", "80211"]);
+    check(&["-m", "16apsk", "-p", "20", "--mapping", "dvbs2"]);
+}
+
+#[test]
+fn test_unsupported() {
+    // Demodulate rejects the modulations it can't receive yet.
+    for m in &["bfsk", "msk", "mfsk", "oqpsk", "dcqpsk", "16cpfsk", "dqpsk", "dbpsk"] {
+        assert!(!run("demodulate", &["-m", m], b"").status.success());
+    }
+}
+
+#[test]
+fn test_bad_input() {
+    // Input that ends during the preamble has no signal to lock onto.
+    let wave = run("modulate", &["-m", "qpsk"], b"0101");
+    let out = run("demodulate", &["-m", "qpsk", "-p", "20"], &wave.stdout[..96]);
+
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "no signal\n");
+
+    // There's no carrier to mix down at 0 Hz.
+    assert!(!run("demodulate", &["-m", "qpsk", "-c", "0"], b"").status.success());
+}